@@ -0,0 +1,56 @@
+//! Framing of messages on a byte stream: every message is preceded by its length as
+//! a big-endian `u32`.
+
+use std::io::{Read, Write};
+
+use crate::Error;
+
+/// Largest message accepted, which bounds what a peer can make us allocate.
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+pub fn write_frame<W: Write>(writer: &mut W, message: &[u8]) -> Result<(), Error> {
+    if message.len() > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLarge { size: message.len(), max: MAX_MESSAGE_SIZE });
+    }
+    let mut frame = Vec::with_capacity(4 + message.len());
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut prefix = [0u8; 4];
+    reader.read_exact(&mut prefix)?;
+    let size = u32::from_be_bytes(prefix) as usize;
+    if size > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLarge { size, max: MAX_MESSAGE_SIZE });
+    }
+    let mut message = vec![0u8; size];
+    reader.read_exact(&mut message)?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn frame_test() {
+        let mut buf = vec![];
+        write_frame(&mut buf, b"hello").unwrap();
+        write_frame(&mut buf, b"").unwrap();
+        assert_eq!(buf.len(), 4 + 5 + 4);
+
+        let mut reader = Cursor::new(buf);
+        assert_eq!(read_frame(&mut reader).unwrap(), b"hello");
+        assert_eq!(read_frame(&mut reader).unwrap(), b"");
+        assert!(matches!(read_frame(&mut reader), Err(Error::Io(_))));
+
+        let mut reader = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        assert!(matches!(read_frame(&mut reader), Err(Error::MessageTooLarge { .. })));
+    }
+}
@@ -0,0 +1,90 @@
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::noise::{self, Cipher, NodeKey, PublicKey};
+use crate::Error;
+
+/// Encrypted TCP connection to a peer carrying framed messages. Reading and writing
+/// can go on concurrently through a clone of the connection.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    remote_key: PublicKey,
+    cipher: Arc<Cipher>,
+}
+
+impl Connection {
+    /// Connects to `addr`, trying every address it resolves to in turn, and shakes
+    /// hands with `key`. `timeout` bounds the connection and the handshake each.
+    pub fn connect(addr: &str, timeout: Duration, key: &NodeKey) -> Result<Self, Error> {
+        let mut last_err = None;
+        for socket_addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&socket_addr, timeout) {
+                Ok(stream) => return Self::handshake(stream, timeout, |stream| noise::initiate(stream, key)),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.map(Error::Io).unwrap_or_else(|| Error::UnresolvedAddress(addr.to_string())))
+    }
+
+    /// Shakes hands with `key` on a connection a peer opened.
+    pub fn accept(stream: TcpStream, timeout: Duration, key: &NodeKey) -> Result<Self, Error> {
+        Self::handshake(stream, timeout, |stream| noise::respond(stream, key))
+    }
+
+    fn handshake<F>(mut stream: TcpStream, timeout: Duration, handshake: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&mut TcpStream) -> Result<(Cipher, PublicKey), Error>,
+    {
+        stream.set_nodelay(true)?;
+        let peer_addr = stream.peer_addr()?;
+        // A peer must not be able to stall the handshake forever.
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let (cipher, remote_key) = handshake(&mut stream)?;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+        Ok(Connection { stream, peer_addr, remote_key, cipher: Arc::new(cipher) })
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Static key the peer proved to hold in the handshake.
+    pub fn remote_key(&self) -> PublicKey {
+        self.remote_key
+    }
+
+    /// Another handle to the same connection, typically one to read from while
+    /// this one is written to.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Connection {
+            stream: self.stream.try_clone()?,
+            peer_addr: self.peer_addr,
+            remote_key: self.remote_key,
+            cipher: self.cipher.clone(),
+        })
+    }
+
+    /// Bounds how long a single write may block, `None` for no limit. Reads are left
+    /// unbounded, as a connection may rightly stay idle.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        Ok(self.stream.set_write_timeout(timeout)?)
+    }
+
+    /// Closes the connection for every handle, which fails their pending reads.
+    pub fn shutdown(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), Error> {
+        self.cipher.send(&mut self.stream, message)
+    }
+
+    pub fn recv(&mut self) -> Result<Vec<u8>, Error> {
+        self.cipher.recv(&mut self.stream)
+    }
+}
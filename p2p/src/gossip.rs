@@ -0,0 +1,126 @@
+//! Fan-out and duplicate suppression for messages spread from peer to peer.
+//!
+//! A node relays a message it has not seen before to a few random peers, which do
+//! the same, so the message reaches the whole network without every node talking to
+//! every other. Remembering what was seen is what stops a message from circling.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use rand::Rng;
+
+/// Set of the most recently inserted keys, forgetting the oldest beyond `capacity`.
+#[derive(Debug)]
+pub struct SeenCache<K> {
+    capacity: usize,
+    keys: HashSet<K>,
+    order: VecDeque<K>,
+}
+
+impl <K: Eq + Hash + Clone>SeenCache<K> {
+    pub fn new(capacity: usize) -> Self {
+        SeenCache { capacity: capacity.max(1), keys: HashSet::new(), order: VecDeque::new() }
+    }
+
+    /// Adds `key`, returning whether it is new.
+    pub fn insert(&mut self, key: K) -> bool {
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+/// Gossip of one kind of message, keyed by `K`.
+#[derive(Debug)]
+pub struct Gossip<K> {
+    fanout: usize,
+    seen: Mutex<SeenCache<K>>,
+}
+
+impl <K: Eq + Hash + Clone>Gossip<K> {
+    /// Relays to `fanout` peers at most and remembers the last `capacity` keys.
+    pub fn new(fanout: usize, capacity: usize) -> Self {
+        Gossip { fanout, seen: Mutex::new(SeenCache::new(capacity)) }
+    }
+
+    /// Records the message with `key` as seen, returning whether it is new. Only new
+    /// messages are worth handling, let alone relaying.
+    pub fn mark_seen(&self, key: K) -> bool {
+        self.seen.lock().unwrap().insert(key)
+    }
+
+    pub fn is_seen(&self, key: &K) -> bool {
+        self.seen.lock().unwrap().contains(key)
+    }
+
+    /// Picks the peers to relay a message to among `peers`, at random, each in
+    /// proportion to the positive weight it comes with.
+    pub fn pick<T>(&self, peers: Vec<(T, f64)>) -> Vec<T> {
+        // Weighted sampling without replacement: every peer draws a key, the highest
+        // keys win.
+        let mut rng = rand::thread_rng();
+        let mut keyed: Vec<(f64, T)> = peers.into_iter()
+            .map(|(peer, weight)| (rng.gen::<f64>().powf(1.0 / weight.max(f64::MIN_POSITIVE)), peer))
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        keyed.into_iter().take(self.fanout).map(|(_, peer)| peer).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seen_cache_test() {
+        let mut cache = SeenCache::new(2);
+        assert!(cache.insert(1));
+        assert!(!cache.insert(1));
+        assert!(cache.insert(2));
+        assert!(cache.insert(3));
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&1));
+        assert!(cache.contains(&2) && cache.contains(&3));
+        // Forgotten keys count as new again.
+        assert!(cache.insert(1));
+    }
+
+    #[test]
+    fn pick_test() {
+        let gossip = Gossip::<u64>::new(3, 10);
+        let picked = gossip.pick((0..10).map(|peer| (peer, 1.0)).collect());
+        assert_eq!(picked.len(), 3);
+        assert_eq!(picked.iter().collect::<HashSet<_>>().len(), 3);
+        assert_eq!(gossip.pick(vec![(1, 1.0), (2, 1.0)]).len(), 2);
+
+        // Heavier peers are picked far more often.
+        let heavy = (0..100)
+            .filter(|_| gossip.pick(vec![(0, 1.0), (1, 1.0), (2, 1.0), (3, 100.0)]).contains(&3))
+            .count();
+        assert!(heavy > 90);
+
+        assert!(gossip.mark_seen(7));
+        assert!(gossip.is_seen(&7));
+        assert!(!gossip.mark_seen(7));
+    }
+}
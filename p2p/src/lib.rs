@@ -1,14 +1,39 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
-}
+//! Length-prefixed message transport over TCP, encrypted and authenticated with the
+//! Noise protocol.
+//!
+//! Messages are opaque byte strings to this crate; the node encodes its protobuf
+//! requests and responses before handing them over.
+
+use thiserror::Error;
+
+pub mod codec;
+pub mod connection;
+pub mod gossip;
+pub mod noise;
+pub mod server;
+
+pub use connection::Connection;
+pub use gossip::Gossip;
+pub use noise::{NodeKey, PublicKey};
+pub use server::{Reply, Server};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Message of {size} bytes exceeds the limit of {max}")]
+    MessageTooLarge {
+        size: usize,
+        max: usize,
+    },
+
+    #[error("Failed to resolve peer address {0}")]
+    UnresolvedAddress(String),
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[error(transparent)]
+    Noise(#[from] snow::Error),
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
+    #[error("Malformed encrypted message")]
+    MalformedMessage,
 }
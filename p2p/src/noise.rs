@@ -0,0 +1,240 @@
+//! Encryption and authentication of connections with the Noise protocol.
+//!
+//! Both sides run the XX handshake with their static node keys, after which each
+//! knows the key of the other and messages go encrypted. Noise messages are at most
+//! 64 KiB, so a message is sent as its encrypted length followed by as many
+//! encrypted chunks as it takes.
+
+use std::fmt::{self, Debug, Formatter};
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+use snow::{Builder, HandshakeState, StatelessTransportState};
+use x25519_dalek::StaticSecret;
+
+use crate::codec::{read_frame, write_frame, MAX_MESSAGE_SIZE};
+use crate::Error;
+
+/// Handshake pattern, DH function, cipher and hash used by every connection.
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Largest Noise message.
+const MAX_NOISE_MESSAGE: usize = 65535;
+
+/// Bytes the cipher adds to every message.
+const TAG_LEN: usize = 16;
+
+/// Largest plaintext carried by a single Noise message.
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - TAG_LEN;
+
+/// Public static key of a node, which identifies it.
+pub type PublicKey = [u8; 32];
+
+/// Static X25519 key pair of a node.
+#[derive(Clone)]
+pub struct NodeKey {
+    secret: [u8; 32],
+    public: PublicKey,
+}
+
+impl NodeKey {
+    pub fn generate() -> Self {
+        Self::from_secret(rand::random())
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let public = x25519_dalek::PublicKey::from(&StaticSecret::from(secret)).to_bytes();
+        NodeKey { secret, public }
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+}
+
+// Keeps the secret out of logs.
+impl Debug for NodeKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeKey").field("public", &self.public).finish()
+    }
+}
+
+/// Encryption state of an established connection. Sending and receiving each keep
+/// their own nonce and may go on concurrently.
+pub struct Cipher {
+    state: StatelessTransportState,
+    /// Next nonce to send with, held while a message is written.
+    send_nonce: Mutex<u64>,
+    /// Next nonce to expect, held while a message is read.
+    recv_nonce: Mutex<u64>,
+}
+
+impl Debug for Cipher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher").finish_non_exhaustive()
+    }
+}
+
+impl Cipher {
+    /// Encrypts `message` and writes it to `writer`.
+    pub fn send<W: Write>(&self, writer: &mut W, message: &[u8]) -> Result<(), Error> {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(Error::MessageTooLarge { size: message.len(), max: MAX_MESSAGE_SIZE });
+        }
+        let mut nonce = self.send_nonce.lock().unwrap();
+        let mut frames = vec![];
+        let length = (message.len() as u32).to_be_bytes();
+        for chunk in std::iter::once(length.as_slice()).chain(message.chunks(MAX_CHUNK)) {
+            let mut encrypted = vec![0u8; chunk.len() + TAG_LEN];
+            let size = self.state.write_message(*nonce, chunk, &mut encrypted)?;
+            *nonce += 1;
+            write_frame(&mut frames, &encrypted[..size])?;
+        }
+        writer.write_all(&frames)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a message from `reader` and decrypts it.
+    pub fn recv<R: Read>(&self, reader: &mut R) -> Result<Vec<u8>, Error> {
+        let mut nonce = self.recv_nonce.lock().unwrap();
+        let mut read_chunk = |buf: &mut Vec<u8>| -> Result<usize, Error> {
+            let encrypted = read_frame(reader)?;
+            let start = buf.len();
+            buf.resize(start + encrypted.len(), 0);
+            let size = self.state.read_message(*nonce, &encrypted, &mut buf[start..])?;
+            *nonce += 1;
+            buf.truncate(start + size);
+            Ok(size)
+        };
+
+        let mut length = vec![];
+        read_chunk(&mut length)?;
+        let length: [u8; 4] = length.try_into().map_err(|_| Error::MalformedMessage)?;
+        let size = u32::from_be_bytes(length) as usize;
+        if size > MAX_MESSAGE_SIZE {
+            return Err(Error::MessageTooLarge { size, max: MAX_MESSAGE_SIZE });
+        }
+        let mut message = Vec::with_capacity(size);
+        while message.len() < size {
+            if read_chunk(&mut message)? == 0 {
+                return Err(Error::MalformedMessage);
+            }
+        }
+        if message.len() != size {
+            return Err(Error::MalformedMessage);
+        }
+        Ok(message)
+    }
+}
+
+/// Runs the handshake as the side that connected, returning the cipher and the
+/// key of the peer.
+pub fn initiate<S: Read + Write>(stream: &mut S, key: &NodeKey) -> Result<(Cipher, PublicKey), Error> {
+    let mut handshake = builder(key).build_initiator()?;
+    // -> e
+    write_handshake(stream, &mut handshake)?;
+    // <- e, ee, s, es
+    read_handshake(stream, &mut handshake)?;
+    // -> s, se
+    write_handshake(stream, &mut handshake)?;
+    finish(handshake)
+}
+
+/// Runs the handshake as the side that accepted the connection.
+pub fn respond<S: Read + Write>(stream: &mut S, key: &NodeKey) -> Result<(Cipher, PublicKey), Error> {
+    let mut handshake = builder(key).build_responder()?;
+    read_handshake(stream, &mut handshake)?;
+    write_handshake(stream, &mut handshake)?;
+    read_handshake(stream, &mut handshake)?;
+    finish(handshake)
+}
+
+fn builder(key: &NodeKey) -> Builder<'_> {
+    // The parameters are a constant known to be valid.
+    Builder::new(NOISE_PARAMS.parse().unwrap()).local_private_key(&key.secret)
+}
+
+fn write_handshake<W: Write>(writer: &mut W, handshake: &mut HandshakeState) -> Result<(), Error> {
+    let mut message = vec![0u8; MAX_NOISE_MESSAGE];
+    let size = handshake.write_message(&[], &mut message)?;
+    write_frame(writer, &message[..size])
+}
+
+fn read_handshake<R: Read>(reader: &mut R, handshake: &mut HandshakeState) -> Result<(), Error> {
+    let message = read_frame(reader)?;
+    let mut payload = vec![0u8; message.len()];
+    handshake.read_message(&message, &mut payload)?;
+    Ok(())
+}
+
+fn finish(handshake: HandshakeState) -> Result<(Cipher, PublicKey), Error> {
+    let remote_key: PublicKey = handshake.get_remote_static()
+        .ok_or(Error::MalformedMessage)?
+        .try_into()
+        .map_err(|_| Error::MalformedMessage)?;
+    let cipher = Cipher {
+        state: handshake.into_stateless_transport_mode()?,
+        send_nonce: Mutex::new(0),
+        recv_nonce: Mutex::new(0),
+    };
+    Ok((cipher, remote_key))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn handshake_test() {
+        let (alice, bob) = (NodeKey::generate(), NodeKey::generate());
+        assert_eq!(NodeKey::from_secret(alice.secret).public(), alice.public());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responder = {
+            let bob = bob.clone();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let (cipher, remote_key) = respond(&mut stream, &bob).unwrap();
+                let message = cipher.recv(&mut stream).unwrap();
+                cipher.send(&mut stream, &message).unwrap();
+                remote_key
+            })
+        };
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let (cipher, remote_key) = initiate(&mut stream, &alice).unwrap();
+        assert_eq!(remote_key, bob.public());
+
+        // Spans several Noise messages.
+        let message: Vec<u8> = (0..3 * MAX_CHUNK + 7).map(|i| i as u8).collect();
+        cipher.send(&mut stream, &message).unwrap();
+        assert_eq!(cipher.recv(&mut stream).unwrap(), message);
+        assert_eq!(responder.join().unwrap(), alice.public());
+    }
+
+    #[test]
+    fn tamper_test() {
+        let (alice, bob) = (NodeKey::generate(), NodeKey::generate());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responder = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (cipher, _) = respond(&mut stream, &bob).unwrap();
+            cipher.recv(&mut stream)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let (cipher, _) = initiate(&mut stream, &alice).unwrap();
+        let mut frames = vec![];
+        cipher.send(&mut frames, b"hello").unwrap();
+        let last = frames.len() - 1;
+        frames[last] ^= 1;
+        stream.write_all(&frames).unwrap();
+        assert!(matches!(responder.join().unwrap(), Err(Error::Noise(_))));
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::connection::Connection;
+use crate::noise::{NodeKey, PublicKey};
+use crate::Error;
+
+/// Most requests of a single connection handled at once. The next request is only
+/// read once one of them has been answered.
+pub const MAX_IN_FLIGHT: usize = 16;
+
+/// How long a peer gets to complete the handshake.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What to do after handling a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// Send the message back.
+    Message(Vec<u8>),
+    /// Send the message back, then close the connection.
+    Close(Vec<u8>),
+}
+
+/// Accepts peer connections and answers every request on them.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    key: NodeKey,
+}
+
+impl Server {
+    /// Listens on `addr` for peers, which connections are secured with `key` for.
+    pub fn bind(addr: impl ToSocketAddrs, key: NodeKey) -> Result<Self, Error> {
+        Ok(Server { listener: TcpListener::bind(addr)?, key })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves every accepted connection on a thread of its own, once the peer has
+    /// shaken hands. `accept` makes the handler of a connection from the address and
+    /// the authenticated key of the peer, and each request on it is answered with
+    /// what that handler replies. Requests of a connection are handled concurrently,
+    /// so their answers may go out in any order; the messages have to say themselves
+    /// which request they answer. Never returns.
+    pub fn serve<A, H>(self, accept: A)
+    where
+        A: Fn(SocketAddr, PublicKey) -> H + Send + Sync + 'static,
+        H: Fn(Vec<u8>) -> Reply + Send + Sync + 'static,
+    {
+        let accept = Arc::new(accept);
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            let accept = accept.clone();
+            let key = self.key.clone();
+            thread::spawn(move || {
+                if let Err(err) = serve_connection(stream, &key, accept.as_ref()) {
+                    debug!("Dropped connection: {err}");
+                }
+            });
+        }
+    }
+}
+
+fn serve_connection<A, H>(stream: TcpStream, key: &NodeKey, accept: &A) -> Result<(), Error>
+where
+    A: Fn(SocketAddr, PublicKey) -> H,
+    H: Fn(Vec<u8>) -> Reply + Send + Sync + 'static,
+{
+    let mut reader = Connection::accept(stream, HANDSHAKE_TIMEOUT, key)?;
+    let handler = Arc::new(accept(reader.peer_addr(), reader.remote_key()));
+    let writer = Arc::new(Mutex::new(reader.try_clone()?));
+    let in_flight = Arc::new(InFlight::default());
+    loop {
+        let request = match reader.recv() {
+            Ok(request) => request,
+            // The peer hung up between requests.
+            Err(Error::Io(err)) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        in_flight.acquire();
+        let handler = handler.clone();
+        let writer = writer.clone();
+        let in_flight = in_flight.clone();
+        thread::spawn(move || {
+            let (response, close) = match handler(request) {
+                Reply::Message(response) => (response, false),
+                Reply::Close(response) => (response, true),
+            };
+            let mut writer = writer.lock().unwrap();
+            if let Err(err) = writer.send(&response) {
+                debug!("Failed to send response: {err}");
+            }
+            if close {
+                // Also ends the loop reading requests.
+                writer.shutdown();
+            }
+            drop(writer);
+            in_flight.release();
+        });
+    }
+}
+
+/// Counts the requests of a connection being handled, up to `MAX_IN_FLIGHT`.
+#[derive(Debug, Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    released: Condvar,
+}
+
+impl InFlight {
+    fn acquire(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count >= MAX_IN_FLIGHT {
+            count = self.released.wait(count).unwrap();
+        }
+        *count += 1;
+    }
+
+    fn release(&self) {
+        *self.count.lock().unwrap() -= 1;
+        self.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn serve_test() {
+        let server = Server::bind("127.0.0.1:0", NodeKey::generate()).unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let key = NodeKey::generate();
+        let public = key.public();
+        thread::spawn(move || server.serve(move |_, remote_key| move |mut request: Vec<u8>| {
+            if remote_key != public {
+                return Reply::Close(b"stranger".to_vec());
+            }
+            match request.as_slice() {
+                b"slow" => thread::sleep(Duration::from_millis(200)),
+                b"bye" => return Reply::Close(request),
+                _ => {}
+            }
+            request.reverse();
+            Reply::Message(request)
+        }));
+
+        let mut connection = Connection::connect(&addr, Duration::from_secs(1), &key).unwrap();
+        connection.send(b"ping").unwrap();
+        assert_eq!(connection.recv().unwrap(), b"gnip");
+
+        // The slow request does not hold up the one sent after it.
+        connection.send(b"slow").unwrap();
+        connection.send(b"fast").unwrap();
+        assert_eq!(connection.recv().unwrap(), b"tsaf");
+        assert_eq!(connection.recv().unwrap(), b"wols");
+
+        let mut other = Connection::connect(&addr, Duration::from_secs(1), &key).unwrap();
+        other.send(b"pong").unwrap();
+        assert_eq!(other.recv().unwrap(), b"gnop");
+
+        // The handler knows who it is talking to.
+        let mut stranger = Connection::connect(&addr, Duration::from_secs(1), &NodeKey::generate()).unwrap();
+        stranger.send(b"ping").unwrap();
+        assert_eq!(stranger.recv().unwrap(), b"stranger");
+
+        connection.send(b"bye").unwrap();
+        assert_eq!(connection.recv().unwrap(), b"bye");
+        assert!(connection.recv().is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use std::fmt::Debug;
+use std::sync::atomic::AtomicBool;
+
+use crate::error::Error;
+use crate::schema::v1::BlockHeader;
+
+/// Rules deciding who may produce a block and how it proves that, on top of the
+/// engine independent checks in `BlockValidator`.
+///
+/// `ancestors` is always ordered by height, ends with the parent of the header and
+/// holds up to `lookback()` headers.
+pub trait Consensus: Debug + Clone + Send + Sync + 'static {
+    /// Number of trailing ancestor headers the engine looks at.
+    fn lookback(&self) -> usize;
+
+    /// Fills in the fields of a new header the engine is responsible for.
+    fn prepare(&self, ancestors: &[BlockHeader], header: &mut BlockHeader) -> Result<(), Error>;
+
+    /// Seals a prepared header, or returns `None` if `cancel` is set first or this
+    /// node may not seal it.
+    fn seal(&self, header: BlockHeader, cancel: &AtomicBool) -> Result<Option<BlockHeader>, Error>;
+
+    /// Checks the fields of `header` the engine is responsible for, `now` being the
+    /// local clock.
+    fn verify_header(&self, ancestors: &[BlockHeader], header: &BlockHeader, now: u64) -> Result<(), Error>;
+}
@@ -0,0 +1,170 @@
+//! Genesis spec a chain starts from, loaded from a JSON file.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::consensus::{params::ConsensusParams, pow::MIN_DIFFICULTY};
+use crate::error::Error;
+use crate::schema::v1::{Block, BlockHeader};
+use crate::types::{Address, Hash};
+use crate::utils;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Genesis {
+    pub chain_id: String,
+    pub timestamp: u64,
+    #[serde(default = "default_difficulty")]
+    pub difficulty: u64,
+    #[serde(default)]
+    pub consensus: ConsensusParams,
+    #[serde(default)]
+    pub engine: EngineSpec,
+    #[serde(default)]
+    pub alloc: BTreeMap<Address, u64>,
+}
+
+/// Consensus engine sealing the blocks of the chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineSpec {
+    #[default]
+    ProofOfWork,
+    /// Validators seal blocks in turn, in the order listed.
+    ProofOfAuthority {
+        validators: Vec<Address>,
+    },
+}
+
+fn default_difficulty() -> u64 {
+    MIN_DIFFICULTY
+}
+
+impl Genesis {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Commitment to the chain id, allocations, consensus params and engine, stored as
+    /// the genesis parent hash so that the genesis hash changes whenever any of them does.
+    pub fn spec_hash(&self) -> Hash {
+        let mut data = self.chain_id.as_bytes().to_vec();
+        for (account, balance) in self.alloc.iter() {
+            data.extend_from_slice(account.as_slice());
+            data.extend_from_slice(&balance.to_be_bytes());
+        }
+        // Plain structs and enums, which serialize the same way on every node.
+        let rules = serde_json::to_vec(&(&self.consensus, &self.engine)).expect("genesis spec serializes");
+        data.extend_from_slice(&rules);
+        utils::hash(&data)
+    }
+
+    /// The height-0 block. It only depends on the spec, so every node builds the same one.
+    pub fn block(&self) -> Block {
+        let header = BlockHeader {
+            parent_hash: self.spec_hash().into(),
+            height: 0,
+            nonce: 0,
+            timestamp: self.timestamp,
+            author: Address::default().into(),
+            txs_root: Hash::default().into(),
+            difficulty: self.difficulty,
+            signature: vec![],
+        };
+        Block::new(Some(header), vec![])
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.block().header.as_ref().map(BlockHeader::hash).unwrap_or_default()
+    }
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            chain_id: "atman-devnet".to_string(),
+            timestamp: 0,
+            difficulty: MIN_DIFFICULTY,
+            consensus: ConsensusParams::default(),
+            engine: EngineSpec::default(),
+            alloc: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Bytes;
+
+    #[test]
+    fn load_test() {
+        let path = std::env::temp_dir().join(format!("atman-genesis-{}.json", utils::gen_random_number::<u64>()));
+        fs::write(&path, r#"{
+            "chain_id": "atman-testnet",
+            "timestamp": 1700000000,
+            "consensus": { "block_reward": 50, "retarget": { "interval": 100 } },
+            "alloc": {
+                "0x000036755a024ef491b6710fe765e06e33a616f83b8a33c6a1963ab20f6e5bdb": 1000
+            }
+        }"#).unwrap();
+
+        let genesis = Genesis::load(&path).unwrap();
+        assert_eq!(genesis.chain_id, "atman-testnet");
+        assert_eq!(genesis.difficulty, MIN_DIFFICULTY);
+        assert_eq!(genesis.consensus.block_reward, 50);
+        assert_eq!(genesis.consensus.retarget.interval, 100);
+        assert_eq!(genesis.consensus.transfer_gas, ConsensusParams::default().transfer_gas);
+        assert_eq!(genesis.engine, EngineSpec::ProofOfWork);
+        assert_eq!(genesis.alloc.values().sum::<u64>(), 1000);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn engine_test() {
+        let genesis: Genesis = serde_json::from_str(r#"{
+            "chain_id": "atman-poa",
+            "timestamp": 0,
+            "engine": {
+                "type": "proof_of_authority",
+                "validators": ["0x000036755a024ef491b6710fe765e06e33a616f83b8a33c6a1963ab20f6e5bdb"]
+            }
+        }"#).unwrap();
+        assert!(matches!(&genesis.engine, EngineSpec::ProofOfAuthority { validators } if validators.len() == 1));
+    }
+
+    #[test]
+    fn hash_test() {
+        let mut genesis = Genesis::default();
+        genesis.alloc.insert(Bytes::<32>::new_for_test(), 1000);
+        assert_eq!(genesis.hash(), genesis.clone().hash());
+
+        let mut other = genesis.clone();
+        other.alloc.insert(Bytes::<32>::new_for_test(), 1);
+        assert_ne!(genesis.hash(), other.hash());
+
+        let mut other = genesis.clone();
+        other.chain_id = "other".to_string();
+        assert_ne!(genesis.hash(), other.hash());
+
+        let mut other = genesis.clone();
+        other.engine = EngineSpec::ProofOfAuthority { validators: vec![Bytes::<32>::new_for_test()] };
+        assert_ne!(genesis.hash(), other.hash());
+
+        let mut other = genesis.clone();
+        other.engine = EngineSpec::ProofOfAuthority { validators: vec![] };
+        assert_ne!(genesis.hash(), other.hash());
+
+        let mut other = genesis.clone();
+        other.consensus.block_reward += 1;
+        assert_ne!(genesis.hash(), other.hash());
+
+        let mut other = genesis.clone();
+        other.consensus.retarget.interval += 1;
+        assert_ne!(genesis.hash(), other.hash());
+    }
+}
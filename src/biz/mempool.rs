@@ -0,0 +1,473 @@
+//! Pool of pending transactions waiting to be packed into a block.
+//!
+//! Txs are keyed by `Tx::id()` and indexed per sender by version. A sender's txs are
+//! ready once their versions continue the sender's version in `State` without a gap;
+//! anything after a gap stays queued as a future tx until the gap is filled.
+//!
+//! The pool is bounded globally and per sender, evicting its cheapest txs first, and
+//! forgets txs whose `Tx.timestamp` is older than the configured TTL. A tx can replace
+//! the pooled one with the same sender and version by bidding a high enough gas price.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::consensus::params::ConsensusParams;
+use crate::error::Error;
+use crate::schema::v1::{Block, SignedTx};
+use crate::types::{Address, Hash};
+use crate::utils;
+
+use super::state::State;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MempoolConfig {
+    /// Percentage a replacement must raise the gas price of the tx it replaces by.
+    pub price_bump: u64,
+    /// Most txs the pool holds.
+    pub max_txs: usize,
+    /// Most txs the pool holds for a single sender.
+    pub max_txs_per_sender: usize,
+    /// Seconds after `Tx.timestamp` a tx is dropped if it has not been packed.
+    pub tx_ttl: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            price_bump: 10,
+            max_txs: 4096,
+            max_txs_per_sender: 64,
+            tx_ttl: 3 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mempool {
+    config: MempoolConfig,
+    params: ConsensusParams,
+    inner: Arc<RwLock<Pool>>,
+}
+
+#[derive(Debug, Default)]
+struct Pool {
+    txs: HashMap<Hash, SignedTx>,
+    by_sender: HashMap<Address, BTreeMap<u64, Hash>>,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig, params: ConsensusParams) -> Self {
+        Mempool { config, params, inner: Default::default() }
+    }
+
+    /// Validates `tx` against `state` and adds it to the pool, returning its id.
+    pub fn add<S: State>(&self, tx: SignedTx, state: &S) -> Result<Hash, Error> {
+        self.add_at(tx, state, utils::unix_timestamp())
+    }
+
+    fn add_at<S: State>(&self, tx: SignedTx, state: &S, now: u64) -> Result<Hash, Error> {
+        tx.verify()?;
+        let raw_tx = tx.raw_tx()?;
+        self.params.validate_tx(raw_tx)?;
+        let id = raw_tx.id();
+        let sender = raw_tx.sender()?;
+
+        if raw_tx.timestamp.saturating_add(self.config.tx_ttl) < now {
+            return Err(Error::TxExpired { id, timestamp: raw_tx.timestamp });
+        }
+        let max = now.saturating_add(self.config.tx_ttl);
+        if raw_tx.timestamp > max {
+            return Err(Error::TimestampTooNew { timestamp: raw_tx.timestamp, max });
+        }
+
+        let current = state.version_of(&sender);
+        if raw_tx.version <= current {
+            return Err(Error::StaleTxVersion { account: sender, current, actual: raw_tx.version });
+        }
+        if state.balance_of(&sender) < raw_tx.total_cost()? {
+            return Err(Error::InsufficientBalance(sender));
+        }
+
+        let mut pool = self.inner.write().unwrap();
+        pool.evict_expired(now, self.config.tx_ttl);
+        if pool.txs.contains_key(&id) {
+            return Err(Error::DuplicateTx(id));
+        }
+
+        let replaced = pool.by_sender.get(&sender).and_then(|versions| versions.get(&raw_tx.version)).copied();
+        match replaced {
+            Some(replaced) => {
+                let old_price = pool.gas_price(&replaced);
+                let min_gas_price = old_price.saturating_add((old_price.saturating_mul(self.config.price_bump) / 100).max(1));
+                if raw_tx.gas_price < min_gas_price {
+                    return Err(Error::ReplacementUnderpriced { account: sender, version: raw_tx.version, min_gas_price });
+                }
+                debug!("Tx {id} replaces {replaced}");
+                pool.remove(&replaced);
+            }
+            None => {
+                let sender_txs = pool.by_sender.get(&sender).map(BTreeMap::len).unwrap_or_default();
+                if sender_txs >= self.config.max_txs_per_sender {
+                    pool.make_room(Some(&sender), raw_tx.gas_price)?;
+                }
+                if pool.txs.len() >= self.config.max_txs {
+                    pool.make_room(None, raw_tx.gas_price)?;
+                }
+            }
+        }
+
+        pool.by_sender.entry(sender).or_default().insert(raw_tx.version, id);
+        pool.txs.insert(id, tx);
+        Ok(id)
+    }
+
+    /// Drops every tx whose timestamp is more than the TTL before `now`.
+    pub fn evict_expired(&self, now: u64) {
+        self.inner.write().unwrap().evict_expired(now, self.config.tx_ttl);
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, id: &Hash) -> bool {
+        self.inner.read().unwrap().txs.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().txs.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Up to `limit` ready txs, highest `gas_price` first. A sender's txs always come
+    /// out in version order and stop once their total cost exceeds the balance.
+    pub fn best_txs<S: State>(&self, state: &S, limit: usize) -> Vec<SignedTx> {
+        let pool = self.inner.read().unwrap();
+        let mut heap = BinaryHeap::new();
+        let mut balances = HashMap::new();
+
+        for sender in pool.by_sender.keys() {
+            balances.insert(*sender, state.balance_of(sender));
+            if let Some(candidate) = pool.candidate(sender, state.version_of(sender) + 1) {
+                heap.push(candidate);
+            }
+        }
+
+        let mut best = vec![];
+        while best.len() < limit {
+            let Some(candidate) = heap.pop() else {
+                break;
+            };
+            let tx = &pool.txs[&candidate.id];
+            let Ok(raw_tx) = tx.raw_tx() else {
+                continue;
+            };
+            let balance = balances.get_mut(&candidate.sender).unwrap();
+            let Some(remaining) = raw_tx.total_cost().ok().and_then(|cost| balance.checked_sub(cost)) else {
+                continue;
+            };
+            *balance = remaining;
+            best.push(tx.clone());
+
+            if let Some(next) = pool.candidate(&candidate.sender, raw_tx.version + 1) {
+                heap.push(next);
+            }
+        }
+        best
+    }
+
+    /// Drops the txs packed in `block` along with any tx `state` has made stale.
+    pub fn remove_block<S: State>(&self, block: &Block, state: &S) {
+        let mut pool = self.inner.write().unwrap();
+        for tx in block.txs.iter() {
+            if let Ok(id) = tx.raw_tx_digest() {
+                pool.remove(&id);
+            }
+        }
+
+        let stale: Vec<Hash> = pool.by_sender.iter()
+            .flat_map(|(sender, versions)| {
+                let current = state.version_of(sender);
+                versions.range(..=current).map(|(_, id)| *id)
+            })
+            .collect();
+        for id in stale.iter() {
+            pool.remove(id);
+        }
+    }
+}
+
+impl Pool {
+    fn gas_price(&self, id: &Hash) -> u64 {
+        self.txs.get(id)
+            .and_then(|tx| tx.raw_tx().ok())
+            .map(|tx| tx.gas_price)
+            .unwrap_or_default()
+    }
+
+    /// Evicts the cheapest tx, of `sender` if given, to make room for a tx paying
+    /// `gas_price`, or fails if nothing in the pool pays less than that.
+    fn make_room(&mut self, sender: Option<&Address>, gas_price: u64) -> Result<(), Error> {
+        let cheapest = match sender {
+            Some(sender) => self.by_sender.get(sender)
+                .into_iter()
+                .flat_map(|versions| versions.values())
+                .min_by_key(|id| self.gas_price(id))
+                .copied(),
+            None => self.txs.keys()
+                .min_by_key(|id| self.gas_price(id))
+                .copied(),
+        };
+
+        match cheapest {
+            Some(cheapest) if self.gas_price(&cheapest) < gas_price => {
+                debug!("Evicting tx {cheapest} from the full mempool");
+                self.remove(&cheapest);
+                Ok(())
+            }
+            _ => Err(Error::MempoolFull),
+        }
+    }
+
+    fn evict_expired(&mut self, now: u64, ttl: u64) {
+        let expired: Vec<Hash> = self.txs.iter()
+            .filter(|(_, tx)| tx.raw_tx().map_or(true, |tx| tx.timestamp.saturating_add(ttl) < now))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired.iter() {
+            debug!("Evicting expired tx {id}");
+            self.remove(id);
+        }
+    }
+
+    fn candidate(&self, sender: &Address, version: u64) -> Option<Candidate> {
+        let id = *self.by_sender.get(sender)?.get(&version)?;
+        let tx = self.txs.get(&id)?.raw_tx().ok()?;
+        Some(Candidate { id, sender: *sender, gas_price: tx.gas_price, timestamp: tx.timestamp })
+    }
+
+    fn remove(&mut self, id: &Hash) -> Option<SignedTx> {
+        let tx = self.txs.remove(id)?;
+        let key = tx.raw_tx().and_then(|raw_tx| Ok((raw_tx.sender()?, raw_tx.version)));
+        if let Ok((sender, version)) = key {
+            if let Some(versions) = self.by_sender.get_mut(&sender) {
+                versions.remove(&version);
+                if versions.is_empty() {
+                    self.by_sender.remove(&sender);
+                }
+            }
+        }
+        Some(tx)
+    }
+}
+
+/// Next ready tx of a sender, ordered by gas price and then by age.
+#[derive(Debug, PartialEq, Eq)]
+struct Candidate {
+    id: Hash,
+    sender: Address,
+    gas_price: u64,
+    timestamp: u64,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.gas_price.cmp(&other.gas_price)
+            .then_with(|| other.timestamp.cmp(&self.timestamp))
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wallet::Wallet;
+
+    use super::*;
+    use crate::biz::genesis::Genesis;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::data::memory_state::MemoryState;
+    use crate::schema::v1::Tx;
+    use crate::types::Bytes;
+
+    fn new_tx(wallet: &Wallet, version: u64, gas_price: u64) -> SignedTx {
+        new_tx_at(wallet, version, gas_price, utils::unix_timestamp())
+    }
+
+    fn new_tx_at(wallet: &Wallet, version: u64, gas_price: u64, timestamp: u64) -> SignedTx {
+        let mut tx = Tx::new(wallet.address().into(), Bytes::<32>::new_for_test(), 10, version);
+        tx.gas_price = gas_price;
+        tx.timestamp = timestamp;
+        tx.sign(wallet)
+    }
+
+    fn new_state(wallets: &[&Wallet]) -> MemoryState<ProofOfWork> {
+        let mut genesis = Genesis::default();
+        for wallet in wallets {
+            genesis.alloc.insert(wallet.address().into(), 1000);
+        }
+        MemoryState::new(&genesis, ProofOfWork::default())
+    }
+
+    #[test]
+    fn add_test() {
+        let alice = Wallet::new();
+        let state = new_state(&[&alice]);
+        let mempool = Mempool::default();
+
+        let tx = new_tx(&alice, 1, 1);
+        let id = mempool.add(tx.clone(), &state).unwrap();
+        assert_eq!(id, tx.raw_tx_digest().unwrap());
+        assert!(mempool.contains(&id));
+
+        assert!(matches!(mempool.add(tx, &state), Err(Error::DuplicateTx(_))));
+        assert!(matches!(mempool.add(new_tx(&alice, 1, 1), &state), Err(Error::ReplacementUnderpriced { version: 1, .. })));
+        assert!(matches!(mempool.add(new_tx(&alice, 0, 1), &state), Err(Error::StaleTxVersion { .. })));
+
+        let mut forged = new_tx(&alice, 2, 1);
+        forged.tx.as_mut().unwrap().amount = 999;
+        assert!(mempool.add(forged, &state).is_err());
+
+        let poor = Wallet::new();
+        assert!(matches!(mempool.add(new_tx(&poor, 1, 1), &state), Err(Error::InsufficientBalance(_))));
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn best_txs_test() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let state = new_state(&[&alice, &bob]);
+        let mempool = Mempool::default();
+
+        let alice1 = new_tx(&alice, 1, 1);
+        let alice2 = new_tx(&alice, 2, 5);
+        let alice4 = new_tx(&alice, 4, 9);
+        let bob1 = new_tx(&bob, 1, 3);
+        for tx in [&alice4, &alice2, &bob1, &alice1] {
+            mempool.add(tx.clone(), &state).unwrap();
+        }
+
+        // alice4 waits behind the missing version 3, alice2 behind alice1.
+        assert_eq!(mempool.best_txs(&state, 10), vec![bob1.clone(), alice1.clone(), alice2.clone()]);
+        assert_eq!(mempool.best_txs(&state, 2), vec![bob1.clone(), alice1.clone()]);
+
+        let alice3 = new_tx(&alice, 3, 1);
+        mempool.add(alice3.clone(), &state).unwrap();
+        assert_eq!(mempool.best_txs(&state, 10), vec![bob1, alice1, alice2, alice3, alice4]);
+    }
+
+    #[test]
+    fn remove_block_test() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let state = new_state(&[&alice, &bob]);
+        let mempool = Mempool::default();
+
+        let alice1 = new_tx(&alice, 1, 1);
+        let alice2 = new_tx(&alice, 2, 1);
+        let bob1 = new_tx(&bob, 1, 1);
+        for tx in [&alice1, &alice2, &bob1] {
+            mempool.add(tx.clone(), &state).unwrap();
+        }
+
+        // A competing tx with alice's version 1 makes the pooled one stale.
+        let parent = state.get_block(0).unwrap();
+        let block = Block::new_child_for_test(parent.header().ok(), Address::default(), vec![new_tx(&alice, 1, 7), bob1.clone()]);
+        state.add_block(block.clone()).unwrap();
+        mempool.remove_block(&block, &state);
+
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&alice2.raw_tx_digest().unwrap()));
+        assert_eq!(mempool.best_txs(&state, 10), vec![alice2]);
+    }
+
+    #[test]
+    fn replace_by_fee_test() {
+        let alice = Wallet::new();
+        let state = new_state(&[&alice]);
+        let mempool = Mempool::default();
+
+        let original = new_tx(&alice, 1, 20);
+        mempool.add(original.clone(), &state).unwrap();
+
+        let underpriced = new_tx(&alice, 1, 21);
+        assert!(matches!(
+            mempool.add(underpriced, &state),
+            Err(Error::ReplacementUnderpriced { min_gas_price: 22, .. })
+        ));
+
+        let replacement = new_tx(&alice, 1, 22);
+        mempool.add(replacement.clone(), &state).unwrap();
+        assert_eq!(mempool.len(), 1);
+        assert!(!mempool.contains(&original.raw_tx_digest().unwrap()));
+        assert_eq!(mempool.best_txs(&state, 10), vec![replacement]);
+
+        let mempool = Mempool::new(MempoolConfig { price_bump: u64::MAX, ..Default::default() }, ConsensusParams::default());
+        mempool.add(new_tx(&alice, 1, 20), &state).unwrap();
+        assert!(matches!(mempool.add(new_tx(&alice, 1, 40), &state), Err(Error::ReplacementUnderpriced { .. })));
+    }
+
+    #[test]
+    fn eviction_test() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let state = new_state(&[&alice, &bob]);
+        let config = MempoolConfig { max_txs: 3, max_txs_per_sender: 2, ..Default::default() };
+        let mempool = Mempool::new(config, ConsensusParams::default());
+
+        let alice1 = new_tx(&alice, 1, 5);
+        let alice2 = new_tx(&alice, 2, 3);
+        mempool.add(alice1.clone(), &state).unwrap();
+        mempool.add(alice2.clone(), &state).unwrap();
+
+        // Alice is at her limit, so her third tx must outbid her cheapest one.
+        assert!(matches!(mempool.add(new_tx(&alice, 3, 3), &state), Err(Error::MempoolFull)));
+        let alice3 = new_tx(&alice, 3, 4);
+        mempool.add(alice3.clone(), &state).unwrap();
+        assert!(!mempool.contains(&alice2.raw_tx_digest().unwrap()));
+
+        let bob1 = new_tx(&bob, 1, 2);
+        mempool.add(bob1.clone(), &state).unwrap();
+        assert_eq!(mempool.len(), 3);
+
+        // The pool is full, so the cheapest tx overall makes way.
+        assert!(matches!(mempool.add(new_tx(&bob, 2, 1), &state), Err(Error::MempoolFull)));
+        mempool.add(new_tx(&bob, 2, 6), &state).unwrap();
+        assert!(!mempool.contains(&bob1.raw_tx_digest().unwrap()));
+        assert_eq!(mempool.len(), 3);
+    }
+
+    #[test]
+    fn ttl_test() {
+        let alice = Wallet::new();
+        let state = new_state(&[&alice]);
+        let mempool = Mempool::default();
+        let ttl = MempoolConfig::default().tx_ttl;
+        let now = utils::unix_timestamp();
+
+        let expired = new_tx_at(&alice, 1, 1, now - ttl - 1);
+        assert!(matches!(mempool.add(expired, &state), Err(Error::TxExpired { .. })));
+
+        let future = new_tx_at(&alice, 1, 1, now + ttl + 60);
+        assert!(matches!(mempool.add(future, &state), Err(Error::TimestampTooNew { .. })));
+
+        let old = new_tx_at(&alice, 1, 1, now - ttl + 60);
+        mempool.add(old, &state).unwrap();
+        mempool.evict_expired(now + 30);
+        assert_eq!(mempool.len(), 1);
+        mempool.evict_expired(now + 61);
+        assert!(mempool.is_empty());
+    }
+}
\ No newline at end of file
@@ -1,18 +1,314 @@
-use std::marker;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
+use consensus::Consensus;
+use log::{info, warn};
+use mempool::Mempool;
 use peer_client::PeerClient;
-use state::State;
+use producer::BlockProducer;
+use state::{ChainUpdate, State};
+use sync::Syncer;
 
+use crate::error::Error;
+use crate::schema::v1::{Block, SignedTx};
+use crate::types::Hash;
+use crate::utils;
+
+pub mod consensus;
+pub mod genesis;
+pub mod mempool;
 pub mod peer_client;
+pub mod producer;
 pub mod state;
+pub mod sync;
+pub mod validator;
 
 #[derive(Debug, Clone)]
-pub struct Node<S: State, P: PeerClient> {
-    _marker: marker::PhantomData<(S, P)>
+pub struct Node<S: State, P: PeerClient, C: Consensus> {
+    pub state: S,
+    pub peer_client: P,
+    pub consensus: C,
+    pub mempool: Mempool,
+    pub syncer: Syncer,
+    /// Cancels the block being sealed, set once the tip it builds on moves.
+    pub sealing: Arc<Mutex<Arc<AtomicBool>>>,
+}
+
+impl <S: State, P: PeerClient, C: Consensus>Node<S, P, C> {
+    /// `mempool` has to be built with the consensus params of the chain in `state`.
+    pub fn new(state: S, peer_client: P, consensus: C, mempool: Mempool) -> Self {
+        Node {
+            state,
+            peer_client,
+            consensus,
+            mempool,
+            syncer: Syncer::default(),
+            sealing: Default::default(),
+        }
+    }
+
+    /// Accepts a tx from a client into the mempool and relays it to peers.
+    pub fn submit_tx(&self, tx: SignedTx) -> Result<Hash, Error> {
+        let id = self.mempool.add(tx.clone(), &self.state)?;
+        info!("Accepted tx {id}");
+        self.peer_client.broadcast_tx(tx);
+        Ok(id)
+    }
+
+    /// Adds `block` to the state and brings the mempool in line with the new best
+    /// chain: packed txs are dropped and txs of orphaned blocks are pooled again.
+    pub fn add_block(&self, block: Block) -> Result<ChainUpdate, Error> {
+        let update = self.state.add_block(block.clone())?;
+        if update != ChainUpdate::SideBranch {
+            self.sealing.lock().unwrap().store(true, Ordering::Relaxed);
+        }
+        match &update {
+            ChainUpdate::Extended => self.mempool.remove_block(&block, &self.state),
+            ChainUpdate::SideBranch => {}
+            ChainUpdate::Reorganized { reverted, applied } => {
+                for tx in reverted.iter().flat_map(|block| block.txs.iter()) {
+                    // Txs the new branch also packed, or made stale, are simply refused.
+                    let _ = self.mempool.add(tx.clone(), &self.state);
+                }
+                for block in applied.iter() {
+                    self.mempool.remove_block(block, &self.state);
+                }
+            }
+        }
+        Ok(update)
+    }
+
+    /// Assembles and seals one block on top of the local tip, adds it to the chain
+    /// and announces it to peers. Returns `None` if `stop` is set or the tip moves
+    /// while sealing, or the consensus engine does not let this node seal the block.
+    pub fn produce_block(&self, producer: &BlockProducer, stop: &AtomicBool) -> Result<Option<Block>, Error> {
+        // Installed before assembling, so that no new tip goes unnoticed.
+        let cancel = Arc::new(AtomicBool::new(false));
+        *self.sealing.lock().unwrap() = cancel.clone();
+
+        let mut block = producer.assemble(&self.state, &self.mempool, &self.consensus)?;
+        let header = block.header()?.clone();
+        let sealed = thread::scope(|scope| {
+            let sealing = scope.spawn(|| self.consensus.seal(header, &cancel));
+            while !sealing.is_finished() {
+                if stop.load(Ordering::Relaxed) {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            sealing.join().unwrap()
+        });
+        let Some(header) = sealed? else {
+            return Ok(None);
+        };
+        block.header = Some(header);
+
+        self.add_block(block.clone())?;
+        let header = block.header()?;
+        info!("Produced block {} {} with {} txs, {} left pending", header.height, header.hash(), block.txs.len(), self.mempool.len());
+        self.peer_client.broadcast_block(block.clone());
+        Ok(Some(block))
+    }
+
+    /// Keeps producing blocks until `stop` is set.
+    pub fn run_producer(&self, producer: &BlockProducer, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            // Blocks sealed on a tip that is still catching up would only be orphaned.
+            if self.syncer.is_syncing() {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            self.mempool.evict_expired(utils::unix_timestamp());
+            match self.produce_block(producer, stop) {
+                Ok(Some(_)) => {}
+                // The tip moved, start over on top of it.
+                Ok(None) if self.sealing.lock().unwrap().load(Ordering::Relaxed) => {}
+                // Not our turn to seal, wait for the block of whoever's turn it is.
+                Ok(None) => thread::sleep(Duration::from_secs(1)),
+                Err(err) => warn!("Failed to produce block: {err}"),
+            }
+        }
+    }
 }
 
-impl <S: State, P: PeerClient>Node<S, P> {
-    pub fn new() -> Self {
-        Node { _marker: marker::PhantomData }
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use wallet::Wallet;
+
+    use super::*;
+    use super::genesis::Genesis;
+    use super::peer_client::{Offense, PeerStatus};
+    use super::producer::ProducerConfig;
+    use crate::consensus::params::ConsensusParams;
+    use crate::consensus::poa::ProofOfAuthority;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::data::memory_state::MemoryState;
+    use crate::schema::v1::{BlockBody, BlockHeader, Tx};
+    use crate::types::{Address, Bytes};
+
+    #[derive(Debug, Clone, Default)]
+    struct TestPeerClient {
+        blocks: Arc<Mutex<Vec<Block>>>,
+    }
+
+    impl PeerClient for TestPeerClient {
+        fn known_peers(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn get_block_height(&self, _peer_id: &str) -> Result<u64, Error> {
+            Ok(0)
+        }
+
+        fn get_headers(&self, _peer_id: &str, _from_height: u64, _limit: u32) -> Result<Vec<BlockHeader>, Error> {
+            Ok(vec![])
+        }
+
+        fn get_block_bodies(&self, _peer_id: &str, _hashes: &[Hash]) -> Result<Vec<BlockBody>, Error> {
+            Ok(vec![])
+        }
+
+        fn broadcast_tx(&self, _tx: SignedTx) {}
+
+        fn broadcast_block(&self, block: Block) {
+            self.blocks.lock().unwrap().push(block);
+        }
+
+        fn report(&self, _peer_id: &str, _offense: Offense) {}
+
+        fn peers(&self) -> Vec<PeerStatus> {
+            vec![]
+        }
+
+        fn ban(&self, _peer_id: &str, _duration: Option<Duration>) {}
+
+        fn unban(&self, _peer_id: &str) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn produce_block_test() {
+        let alice = Wallet::new();
+        let miner: Address = Bytes::<32>::new_for_test();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        let node = Node::new(MemoryState::new(&genesis, ProofOfWork::default()), TestPeerClient::default(), ProofOfWork::default(), Mempool::default());
+
+        for version in 1..=3 {
+            let tx = Tx::new(alice.address().into(), Bytes::<32>::new_for_test(), 10, version).sign(&alice);
+            node.submit_tx(tx).unwrap();
+        }
+
+        // Room for two transfers only.
+        let params = ConsensusParams { block_gas_limit: 42, ..Default::default() };
+        let producer = BlockProducer::new(ProducerConfig { coinbase: miner, threads: 1, signer: None }, params);
+        let block = node.produce_block(&producer, &AtomicBool::new(false)).unwrap().unwrap();
+
+        assert_eq!(block.txs.len(), 2);
+        assert_eq!(block.header().unwrap().author, miner.to_vec());
+        assert_eq!(node.state.block_height(), 1);
+        assert_eq!(node.state.version_of(&alice.address().into()), 2);
+        assert_eq!(node.state.balance_of(&miner), block.block_reward(&params).unwrap());
+        assert_eq!(node.mempool.len(), 1);
+        assert_eq!(node.peer_client.blocks.lock().unwrap().as_slice(), &[block]);
+
+        let block = node.produce_block(&producer, &AtomicBool::new(false)).unwrap().unwrap();
+        assert_eq!(block.txs.len(), 1);
+        assert!(node.mempool.is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn produce_poa_block_test() {
+        let validator = Wallet::new();
+        let validators = vec![validator.address().into()];
+        let genesis = Genesis::default();
+        let producer = BlockProducer::new(ProducerConfig { coinbase: Bytes::<32>::new_for_test(), threads: 1, signer: None }, genesis.consensus);
+
+        let outsider = ProofOfAuthority::new(genesis.consensus, validators.clone(), Some(Wallet::new())).unwrap();
+        let node = Node::new(MemoryState::new(&genesis, outsider.clone()), TestPeerClient::default(), outsider, Mempool::default());
+        assert!(node.produce_block(&producer, &AtomicBool::new(false)).unwrap().is_none());
+
+        let consensus = ProofOfAuthority::new(genesis.consensus, validators, Some(validator.clone())).unwrap();
+        let node = Node::new(MemoryState::new(&genesis, consensus.clone()), TestPeerClient::default(), consensus, Mempool::default());
+        let block = node.produce_block(&producer, &AtomicBool::new(false)).unwrap().unwrap();
+        assert_eq!(ProofOfAuthority::recover_sealer(block.header().unwrap()).unwrap(), Address::from(validator.address()));
+        assert_eq!(node.state.block_height(), 1);
+    }
+
+    /// Never seals anything, it only waits to be cancelled.
+    #[derive(Debug, Clone)]
+    struct Stalled;
+
+    impl Consensus for Stalled {
+        fn lookback(&self) -> usize {
+            1
+        }
+
+        fn prepare(&self, _ancestors: &[BlockHeader], _header: &mut BlockHeader) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn seal(&self, _header: BlockHeader, cancel: &AtomicBool) -> Result<Option<BlockHeader>, Error> {
+            while !cancel.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(None)
+        }
+
+        fn verify_header(&self, _ancestors: &[BlockHeader], _header: &BlockHeader, _now: u64) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cancel_sealing_test() {
+        let genesis = Genesis::default();
+        let node = Node::new(MemoryState::new(&genesis, Stalled), TestPeerClient::default(), Stalled, Mempool::default());
+        let producer = BlockProducer::new(ProducerConfig { coinbase: Bytes::<32>::new_for_test(), threads: 1, signer: None }, genesis.consensus);
+        // The attempt holds on to its flag while it runs.
+        let sealing = || Arc::strong_count(&node.sealing.lock().unwrap()) > 1;
+
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let attempt = scope.spawn(|| node.produce_block(&producer, &stop));
+            while !sealing() {
+                thread::yield_now();
+            }
+            let block = Block::new_child_for_test(genesis.block().header.as_ref(), Bytes::<32>::new_for_test(), vec![]);
+            assert_eq!(node.add_block(block).unwrap(), ChainUpdate::Extended);
+            assert!(attempt.join().unwrap().unwrap().is_none());
+
+            let attempt = scope.spawn(|| node.produce_block(&producer, &stop));
+            while !sealing() {
+                thread::yield_now();
+            }
+            stop.store(true, Ordering::Relaxed);
+            assert!(attempt.join().unwrap().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn reorg_returns_txs_to_mempool_test() {
+        let alice = Wallet::new();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        let node = Node::new(MemoryState::new(&genesis, ProofOfWork::default()), TestPeerClient::default(), ProofOfWork::default(), Mempool::default());
+        let genesis_header = genesis.block().header.unwrap();
+
+        let tx = Tx::new(alice.address().into(), Bytes::<32>::new_for_test(), 10, 1).sign(&alice);
+        node.submit_tx(tx.clone()).unwrap();
+        let block = Block::new_child_for_test(Some(&genesis_header), Bytes::<32>::new_for_test(), vec![tx.clone()]);
+        node.add_block(block).unwrap();
+        assert!(node.mempool.is_empty());
+
+        let fork1 = Block::new_child_for_test(Some(&genesis_header), Bytes::<32>::new_for_test(), vec![]);
+        node.add_block(fork1.clone()).unwrap();
+        let fork2 = Block::new_child_for_test(fork1.header.as_ref(), Bytes::<32>::new_for_test(), vec![]);
+        assert!(matches!(node.add_block(fork2).unwrap(), ChainUpdate::Reorganized { .. }));
+
+        assert!(node.mempool.contains(&tx.raw_tx_digest().unwrap()));
+    }
+}
@@ -1,15 +1,126 @@
 use std::fmt::Debug;
+use std::time::Duration;
 
-use crate::{error::Error, schema::v1::{Block, SignedTx}};
+use serde::Serialize;
+
+use crate::{error::Error, schema::v1::{Block, BlockBody, BlockHeader, SignedTx}, types::Hash};
 
 pub trait PeerClient: Debug + Clone + Send + Sync + 'static {
+    /// Peers to sync from, the most reputable first. Banned peers are left out.
     fn known_peers(&self) -> Vec<String>;
 
     fn get_block_height(&self, peer_id: &str) -> Result<u64, Error>;
 
-    fn get_blocks(&self, peer_id: &str, from_height: u64) -> Result<Vec<Block>, Error>;
+    /// Headers of the best chain of `peer_id` from `from_height` on, at most `limit`
+    /// of them.
+    fn get_headers(&self, peer_id: &str, from_height: u64, limit: u32) -> Result<Vec<BlockHeader>, Error>;
+
+    /// Bodies of the blocks with `hashes` in the same order, possibly only the first
+    /// few of them.
+    fn get_block_bodies(&self, peer_id: &str, hashes: &[Hash]) -> Result<Vec<BlockBody>, Error>;
 
+    /// Announces a tx the local node accepted to some of its peers, which relay it
+    /// on in turn. Txs announced before are left alone.
     fn broadcast_tx(&self, tx: SignedTx);
 
+    /// Announces a block the local node accepted, like `broadcast_tx`.
     fn broadcast_block(&self, block: Block);
-}
\ No newline at end of file
+
+    /// Lowers the reputation of `peer_id` for `offense`, banning it once it falls
+    /// too low.
+    fn report(&self, peer_id: &str, offense: Offense);
+
+    /// Reputation of the peers connected to or penalized.
+    fn peers(&self) -> Vec<PeerStatus>;
+
+    /// Disconnects `peer_id` and refuses it for `duration`, or for the configured
+    /// ban duration when `None`.
+    fn ban(&self, peer_id: &str, duration: Option<Duration>);
+
+    /// Lifts the ban of `peer_id` and forgets its penalties. Returns whether it was
+    /// banned.
+    fn unban(&self, peer_id: &str) -> bool;
+}
+
+/// Misbehaviour of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Offense {
+    /// Sent something that does not decode, `Error::InvalidP2pMessage`.
+    MalformedMessage,
+    /// Answered a request with the wrong kind of response, `Error::InvalidResponse`.
+    UnexpectedResponse,
+    /// Served or announced a block, header or body that does not validate.
+    InvalidBlock,
+}
+
+impl Offense {
+    /// How much the offense lowers the score of a peer. Mistakes an honest peer is
+    /// unlikely to make weigh more.
+    pub fn penalty(&self) -> f64 {
+        match self {
+            Offense::MalformedMessage => 25.0,
+            Offense::UnexpectedResponse => 10.0,
+            Offense::InvalidBlock => 50.0,
+        }
+    }
+
+    /// The offense of a peer whose block failed to add with `err`: only a block that
+    /// breaks the consensus rules counts. Anything else is no fault of the peer, be it
+    /// a block the node already has or cannot place, a timestamp only clock skew puts
+    /// out of range, or a local failure such as a disk error.
+    pub fn of_block_error(err: &Error) -> Option<Self> {
+        match err {
+            Error::EmptyHeader
+            | Error::InvalidBlockHeader
+            | Error::EmptyRawTx
+            | Error::InvalidTx
+            | Error::InvalidSignature(_)
+            | Error::InvalidSigner { .. }
+            | Error::InvalidParentHash { .. }
+            | Error::InvalidTxsRoot { .. }
+            | Error::InvalidBlockHeight { .. }
+            | Error::InvalidGas { .. }
+            | Error::GasPriceTooLow { .. }
+            | Error::FeeOverflow
+            | Error::BlockGasLimitExceeded { .. }
+            | Error::InvalidTxVersion { .. }
+            | Error::InsufficientBalance(_)
+            | Error::BalanceOverflow(_)
+            | Error::InvalidProofOfWork { .. }
+            | Error::InvalidDifficulty { .. }
+            | Error::TimestampTooOld { .. }
+            | Error::MissingSeal
+            | Error::UnexpectedSealer { .. } => Some(Offense::InvalidBlock),
+            _ => None,
+        }
+    }
+}
+
+/// What the admin interface shows of a peer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerStatus {
+    pub peer_id: String,
+    pub connected: bool,
+    /// 0 for a peer that never misbehaved, lower for every offense, recovering
+    /// over time.
+    pub score: f64,
+    /// Unix timestamp the ban of the peer ends at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banned_until: Option<u64>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn of_block_error_test() {
+        assert_eq!(Offense::of_block_error(&Error::MissingSeal), Some(Offense::InvalidBlock));
+        assert_eq!(Offense::of_block_error(&Error::InvalidDifficulty { expected: 1, actual: 2 }), Some(Offense::InvalidBlock));
+        assert_eq!(Offense::of_block_error(&Error::TimestampTooNew { timestamp: 2, max: 1 }), None);
+        assert_eq!(Offense::of_block_error(&Error::BlockTooEarly { timestamp: 1, earliest: 2 }), None);
+        assert_eq!(Offense::of_block_error(&Error::Io(std::io::Error::other("disk failure"))), None);
+        assert_eq!(Offense::of_block_error(&Error::UnknownParent(Hash::default())), None);
+    }
+}
@@ -0,0 +1,77 @@
+//! Assembles blocks from the mempool on top of the local tip.
+
+use serde::{Deserialize, Serialize};
+
+use crate::consensus::params::ConsensusParams;
+use crate::error::Error;
+use crate::schema::v1::{Block, BlockHeader, SignedTx};
+use crate::types::Address;
+
+use super::consensus::Consensus;
+use super::mempool::Mempool;
+use super::state::State;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProducerConfig {
+    /// Account credited with the block reward and the gas of packed txs.
+    pub coinbase: Address,
+    /// Mining threads, for proof-of-work chains.
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    /// Hex private key of the validator sealing blocks, for proof-of-authority chains.
+    #[serde(default)]
+    pub signer: Option<String>,
+}
+
+fn default_threads() -> usize {
+    1
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockProducer {
+    config: ProducerConfig,
+    params: ConsensusParams,
+}
+
+impl BlockProducer {
+    pub fn new(config: ProducerConfig, params: ConsensusParams) -> Self {
+        BlockProducer { config, params }
+    }
+
+    /// Builds an unsealed block on top of the tip of `state` from the best txs in
+    /// `mempool` that fit in the block gas limit, ready to be sealed by `consensus`.
+    pub fn assemble<S: State, C: Consensus>(&self, state: &S, mempool: &Mempool, consensus: &C) -> Result<Block, Error> {
+        let height = state.block_height() + 1;
+        let from_height = height.saturating_sub(consensus.lookback() as u64);
+        let ancestors = state.get_blocks(from_height)
+            .iter()
+            .map(|block| block.header().cloned())
+            .collect::<Result<Vec<BlockHeader>, Error>>()?;
+
+        let txs = self.pack_txs(mempool.best_txs(state, usize::MAX));
+        let mut block = Block::new(None, txs);
+        let parent_hash = state.last_block_hash().unwrap_or_default();
+        let mut header = BlockHeader::new(parent_hash, height, 0, self.config.coinbase, block.compute_txs_root()?);
+        consensus.prepare(&ancestors, &mut header)?;
+        block.header = Some(header);
+        Ok(block)
+    }
+
+    // `txs` come in sender version order, so stop at the first tx that does not fit
+    // rather than skip it and leave a gap behind.
+    fn pack_txs(&self, txs: Vec<SignedTx>) -> Vec<SignedTx> {
+        let mut gas_used = 0u64;
+        txs.into_iter()
+            .take_while(|tx| {
+                let gas = tx.raw_tx().map(|tx| tx.gas).unwrap_or(u64::MAX);
+                match gas_used.checked_add(gas) {
+                    Some(total) if total <= self.params.block_gas_limit => {
+                        gas_used = total;
+                        true
+                    }
+                    _ => false,
+                }
+            })
+            .collect()
+    }
+}
@@ -1,23 +1,50 @@
 use std::{collections::HashMap, fmt::Debug};
 
-use crate::{error::Error, schema::v1::Block, types::Hash};
+use crate::{error::Error, schema::v1::{Block, BlockHeader}, types::{Address, Hash}};
+
+/// How adding a block changed the best chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainUpdate {
+    /// The block extended the best chain.
+    Extended,
+    /// The block was kept on a side branch with no more work than the best chain.
+    SideBranch,
+    /// The branch of the block overtook the best chain. `reverted` are the blocks
+    /// that left the best chain and `applied` the ones that joined it, both in
+    /// height order.
+    Reorganized { reverted: Vec<Block>, applied: Vec<Block> },
+}
 
 pub trait State: Debug + Clone + Send + Sync + 'static {
     fn block_height(&self) -> u64;
 
-    fn version(&self) -> u64;
-
     fn last_block_hash(&self) -> Option<Hash>;
 
-    fn add_block(&self, block: Block) -> Result<(), Error>;
+    /// Adds a block whose parent is anywhere in the known block tree, switching the
+    /// best chain to its branch if that branch has the most cumulative work.
+    fn add_block(&self, block: Block) -> Result<ChainUpdate, Error>;
+
+    /// Whether the block is known, on the best chain or on a side branch.
+    fn has_block(&self, hash: &Hash) -> bool;
+
+    fn get_block(&self, height: u64) -> Result<Block, Error>;
+
+    fn get_blocks(&self, from_height: u64) -> Vec<Block>;
+
+    /// The block with `hash`, on the best chain or on a side branch.
+    fn get_block_by_hash(&self, hash: &Hash) -> Option<Block>;
 
-    fn get_block(&self, height: u64) -> Option<Block>;
+    /// Headers of the best chain from `from_height` on, at most `limit` of them.
+    fn get_headers(&self, from_height: u64, limit: usize) -> Vec<BlockHeader>;
 
-    fn get_blocks(&self, from_height: u64) -> Vec<Option<Block>>;
+    /// Checks that `headers` form a valid branch of the known block tree, short of
+    /// the txs of the blocks. The first one has to attach to a known block,
+    /// `Error::UnknownParent` otherwise.
+    fn validate_headers(&self, headers: &[BlockHeader]) -> Result<(), Error>;
 
-    fn get_balance(&self) -> u64;
+    fn balance_of(&self, account: &Address) -> u64;
 
-    fn get_balances(&self) -> HashMap<String, u64>;
+    fn version_of(&self, account: &Address) -> u64;
 
-    fn get_version(&self) -> HashMap<String, u64>;
-}
\ No newline at end of file
+    fn get_balances(&self) -> HashMap<Address, u64>;
+}
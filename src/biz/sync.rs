@@ -0,0 +1,463 @@
+//! Catching up with the longest chain known to peers.
+//!
+//! Every round asks the known peers for their height and syncs from the highest one,
+//! header first: a window of headers is validated as a chain before the bodies are
+//! downloaded, in parallel from every peer high enough, and the blocks fed through
+//! `Node::add_block`, whose fork choice decides whether they become the best chain.
+//! Among peers of the same height the most reputable goes first.
+//! A peer whose blocks do not apply is reported to the peer client, which bans it
+//! once it has misbehaved enough; a peer that simply cannot be reached is tried again
+//! next round.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::schema::v1::{Block, BlockHeader};
+use crate::types::Hash;
+
+use super::consensus::Consensus;
+use super::peer_client::{Offense, PeerClient};
+use super::state::State;
+use super::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Seconds between two sync rounds.
+    pub interval: u64,
+    /// Headers asked for at a time, which bounds how far the download runs ahead of
+    /// the blocks applied.
+    pub headers_per_request: u32,
+    /// Bodies asked of one peer at a time.
+    pub bodies_per_request: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig { interval: 10, headers_per_request: 256, bodies_per_request: 32 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SyncStatus {
+    Syncing { height: u64, target: u64 },
+    CaughtUp { height: u64 },
+}
+
+impl Default for SyncStatus {
+    fn default() -> Self {
+        SyncStatus::CaughtUp { height: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Syncer {
+    config: SyncConfig,
+    status: Arc<RwLock<SyncStatus>>,
+}
+
+impl Syncer {
+    pub fn new(config: SyncConfig) -> Self {
+        Syncer { config, ..Default::default() }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval)
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        *self.status.read().unwrap()
+    }
+
+    pub fn set_status(&self, status: SyncStatus) {
+        *self.status.write().unwrap() = status;
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        matches!(self.status(), SyncStatus::Syncing { .. })
+    }
+}
+
+impl <S: State, P: PeerClient, C: Consensus>Node<S, P, C> {
+    /// Runs one sync round against the highest peers and returns the resulting status.
+    /// The round ends early once `stop` is set.
+    pub fn sync(&self, stop: &AtomicBool) -> SyncStatus {
+        let mut peers: Vec<(String, u64)> = self.peer_client.known_peers()
+            .into_iter()
+            .filter_map(|peer_id| match self.peer_client.get_block_height(&peer_id) {
+                Ok(height) => Some((peer_id, height)),
+                Err(err) => {
+                    warn!("Failed to get block height from {peer_id}: {err}");
+                    None
+                }
+            })
+            .collect();
+        peers.sort_by_key(|(_, height)| std::cmp::Reverse(*height));
+
+        // Only a height a peer backed up with its chain counts, so that a peer claiming
+        // more than it has cannot keep the node syncing. Misbehaving peers are reported
+        // by `sync_from`.
+        let mut target = self.state.block_height();
+        for (peer_id, height) in peers.iter() {
+            if *height <= self.state.block_height() {
+                break;
+            }
+            match self.sync_from(peer_id, *height, &peers, stop) {
+                Ok(()) => {
+                    target = *height;
+                    break;
+                }
+                Err(err) => warn!("Failed to sync from {peer_id}: {err}"),
+            }
+        }
+
+        let height = self.state.block_height();
+        let status = if height < target {
+            SyncStatus::Syncing { height, target }
+        } else {
+            SyncStatus::CaughtUp { height }
+        };
+        self.syncer.set_status(status);
+        status
+    }
+
+    /// Syncs up to `target` from `peer_id`, one window of headers at a time: the
+    /// headers are validated as a chain first, then the bodies downloaded from `peers`
+    /// in parallel and the blocks applied. Peers serving anything invalid are
+    /// reported. When the first headers do not attach to the local block tree the
+    /// peer is on another branch, so the download restarts further back, twice as far
+    /// each time, to find the common ancestor.
+    fn sync_from(&self, peer_id: &str, target: u64, peers: &[(String, u64)], stop: &AtomicBool) -> Result<(), Error> {
+        info!("Syncing from {peer_id} up to height {target}");
+        let mut from_height = self.state.block_height() + 1;
+        let mut step = 1;
+        while from_height <= target && !stop.load(Ordering::Relaxed) {
+            self.syncer.set_status(SyncStatus::Syncing { height: self.state.block_height(), target });
+
+            let headers = self.peer_client.get_headers(peer_id, from_height, self.syncer.config.headers_per_request)?;
+            let Some(last_height) = headers.last().map(|header| header.height) else {
+                // The peer claimed to be higher.
+                self.peer_client.report(peer_id, Offense::UnexpectedResponse);
+                return Err(Error::InvalidResponse);
+            };
+            // Anything else would not move the download forward.
+            if !headers.iter().zip(from_height..).all(|(header, height)| header.height == height) {
+                self.peer_client.report(peer_id, Offense::UnexpectedResponse);
+                return Err(Error::InvalidResponse);
+            }
+            match self.state.validate_headers(&headers) {
+                Ok(()) => {}
+                Err(Error::UnknownParent(_)) if from_height > 1 => {
+                    from_height = from_height.saturating_sub(step).max(1);
+                    step *= 2;
+                    continue;
+                }
+                Err(err) => return Err(self.blame(peer_id, err)),
+            }
+
+            let missing: Vec<BlockHeader> = headers.into_iter()
+                .filter(|header| !self.state.has_block(&header.hash()))
+                .collect();
+            for block in self.download_bodies(peer_id, peers, &missing)? {
+                match self.add_block(block) {
+                    // Announced meanwhile.
+                    Ok(_) | Err(Error::KnownBlock(_)) => {}
+                    // The bodies match the headers and their signatures hold, so the
+                    // headers are to blame.
+                    Err(err) => return Err(self.blame(peer_id, err)),
+                }
+            }
+            from_height = last_height + 1;
+        }
+        Ok(())
+    }
+
+    /// Downloads the bodies of `headers` in chunks of `SyncConfig::bodies_per_request`,
+    /// all at once, spreading the chunks over the `peers` high enough to have them.
+    /// What a peer does not serve, or serves wrong, is asked of `peer_id`, which sent
+    /// the headers, instead.
+    fn download_bodies(&self, peer_id: &str, peers: &[(String, u64)], headers: &[BlockHeader]) -> Result<Vec<Block>, Error> {
+        let chunks: Vec<Result<Vec<Block>, Error>> = thread::scope(|scope| {
+            let handles: Vec<_> = headers.chunks(self.syncer.config.bodies_per_request.max(1)).enumerate()
+                .map(|(i, chunk)| {
+                    let last_height = chunk.last().map_or(0, |header| header.height);
+                    let sources: Vec<&str> = peers.iter()
+                        .filter(|(_, height)| *height >= last_height)
+                        .map(|(source, _)| source.as_str())
+                        .collect();
+                    let source = sources.get(i % sources.len().max(1)).copied().unwrap_or(peer_id);
+                    scope.spawn(move || self.download_chunk(source, peer_id, chunk))
+                })
+                .collect();
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(Error::SyncWorkerPanicked)))
+                .collect()
+        });
+        Ok(chunks.into_iter().collect::<Result<Vec<_>, _>>()?.concat())
+    }
+
+    /// Downloads the bodies of `headers` from `source`, falling back to `fallback`.
+    fn download_chunk<'a>(&self, mut source: &'a str, fallback: &'a str, headers: &[BlockHeader]) -> Result<Vec<Block>, Error> {
+        let mut blocks = Vec::with_capacity(headers.len());
+        while blocks.len() < headers.len() {
+            let rest = &headers[blocks.len()..];
+            let hashes: Vec<Hash> = rest.iter().map(|header| header.hash()).collect();
+            let bodies = match self.peer_client.get_block_bodies(source, &hashes) {
+                Ok(bodies) => bodies,
+                Err(err) if source != fallback => {
+                    debug!("Failed to get block bodies from {source}: {err}");
+                    source = fallback;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            let before = blocks.len();
+            for (header, body) in rest.iter().zip(bodies) {
+                let block = Block { header: Some(header.clone()), txs: body.txs };
+                let expected = header.txs_root()?;
+                // The txs root leaves the signatures out, so they are checked here,
+                // where it is still known who served them.
+                let valid = block.compute_txs_root().is_ok_and(|actual| actual == expected)
+                    && block.txs.iter().all(|tx| tx.verify().is_ok());
+                if !valid {
+                    self.peer_client.report(source, Offense::InvalidBlock);
+                    break;
+                }
+                blocks.push(block);
+            }
+            if blocks.len() == before {
+                if source == fallback {
+                    return Err(Error::InvalidResponse);
+                }
+                source = fallback;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Reports `peer_id` for serving what failed with `err`, returning `err`.
+    fn blame(&self, peer_id: &str, err: Error) -> Error {
+        if let Some(offense) = Offense::of_block_error(&err) {
+            self.peer_client.report(peer_id, offense);
+        }
+        err
+    }
+
+    /// Keeps syncing every `SyncConfig::interval` until `stop` is set.
+    pub fn run_sync(&self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            if let SyncStatus::Syncing { height, target } = self.sync(stop) {
+                info!("Sync stopped at height {height} of {target}");
+            }
+            thread::sleep(self.syncer.interval());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Mutex;
+
+    use wallet::Wallet;
+
+    use super::*;
+    use crate::biz::genesis::Genesis;
+    use crate::biz::mempool::Mempool;
+    use crate::biz::peer_client::PeerStatus;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::data::memory_state::MemoryState;
+    use crate::schema::v1::{BlockBody, SignedTx, Tx};
+    use crate::types::{Address, Bytes};
+
+    /// Peers serving fixed chains, at most `batch` headers or bodies per response.
+    /// Reported peers are banned right away.
+    #[derive(Debug, Clone)]
+    struct TestPeerClient {
+        chains: HashMap<String, Vec<Block>>,
+        batch: usize,
+        /// Peers that serve headers from height 1 whatever they are asked for.
+        stuck: HashSet<String>,
+        reports: Arc<Mutex<Vec<(String, Offense)>>>,
+        /// Peers that served bodies.
+        served: Arc<Mutex<HashSet<String>>>,
+    }
+
+    impl TestPeerClient {
+        fn new(chains: HashMap<String, Vec<Block>>, batch: usize) -> Self {
+            TestPeerClient { chains, batch, stuck: Default::default(), reports: Default::default(), served: Default::default() }
+        }
+
+        fn chain(&self, peer_id: &str) -> Result<&Vec<Block>, Error> {
+            self.chains.get(peer_id).ok_or(Error::UnknownPeer(peer_id.to_string()))
+        }
+    }
+
+    impl PeerClient for TestPeerClient {
+        fn known_peers(&self) -> Vec<String> {
+            let reports = self.reports.lock().unwrap();
+            self.chains.keys()
+                .filter(|peer_id| !reports.iter().any(|(reported, _)| reported == *peer_id))
+                .cloned()
+                .collect()
+        }
+
+        fn get_block_height(&self, peer_id: &str) -> Result<u64, Error> {
+            Ok(self.chain(peer_id)?.len() as u64 - 1)
+        }
+
+        fn get_headers(&self, peer_id: &str, from_height: u64, limit: u32) -> Result<Vec<BlockHeader>, Error> {
+            let from_height = if self.stuck.contains(peer_id) { 1 } else { from_height };
+            Ok(self.chain(peer_id)?.iter()
+                .skip(from_height as usize)
+                .take(self.batch.min(limit as usize))
+                .map(|block| block.header().unwrap().clone())
+                .collect())
+        }
+
+        fn get_block_bodies(&self, peer_id: &str, hashes: &[Hash]) -> Result<Vec<BlockBody>, Error> {
+            let chain = self.chain(peer_id)?;
+            self.served.lock().unwrap().insert(peer_id.to_string());
+            Ok(hashes.iter()
+                .take(self.batch)
+                .map_while(|hash| chain.iter().find(|block| block.header().unwrap().hash() == *hash))
+                .map(|block| BlockBody { txs: block.txs.clone() })
+                .collect())
+        }
+
+        fn broadcast_tx(&self, _tx: SignedTx) {}
+
+        fn broadcast_block(&self, _block: Block) {}
+
+        fn report(&self, peer_id: &str, offense: Offense) {
+            self.reports.lock().unwrap().push((peer_id.to_string(), offense));
+        }
+
+        fn peers(&self) -> Vec<PeerStatus> {
+            vec![]
+        }
+
+        fn ban(&self, _peer_id: &str, _duration: Option<Duration>) {}
+
+        fn unban(&self, _peer_id: &str) -> bool {
+            false
+        }
+    }
+
+    fn new_chain(genesis: &Genesis, len: usize) -> Vec<Block> {
+        let state = MemoryState::new(genesis, ProofOfWork::default());
+        let author: Address = Bytes::<32>::new_for_test();
+        for _ in 0..len {
+            let parent = state.get_blocks(0).pop().unwrap();
+            let block = Block::new_child_for_test(Some(parent.header().unwrap()), author, vec![]);
+            state.add_block(block).unwrap();
+        }
+        state.get_blocks(0)
+    }
+
+    #[test]
+    fn sync_test() {
+        let genesis = Genesis::default();
+        let honest = new_chain(&genesis, 5);
+
+        // Claims the highest chain but its first block packs a tx the txs root misses.
+        let mut forged = new_chain(&genesis, 8);
+        let tx = Tx::new(Wallet::new().address().into(), Bytes::<32>::new_for_test(), 1, 1);
+        forged[1].txs.push(tx.sign(&Wallet::new()));
+
+        let chains = HashMap::from([
+            ("honest".to_string(), honest.clone()),
+            ("short".to_string(), new_chain(&genesis, 2)),
+            ("forged".to_string(), forged),
+        ]);
+        let node = Node::new(MemoryState::new(&genesis, ProofOfWork::default()), TestPeerClient::new(chains, 2), ProofOfWork::default(), Mempool::default());
+
+        assert_eq!(node.sync(&AtomicBool::new(false)), SyncStatus::CaughtUp { height: 5 });
+        assert_eq!(*node.peer_client.reports.lock().unwrap(), [("forged".to_string(), Offense::InvalidBlock)]);
+        assert_eq!(node.state.get_blocks(0), honest);
+
+        assert_eq!(node.sync(&AtomicBool::new(false)), SyncStatus::CaughtUp { height: 5 });
+        assert!(!node.syncer.is_syncing());
+    }
+
+    #[test]
+    fn forged_bodies_test() {
+        let alice = Wallet::new();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        let state = MemoryState::new(&genesis, ProofOfWork::default());
+        let author: Address = Bytes::<32>::new_for_test();
+        for version in 1..=4 {
+            let tx = Tx::new(alice.address().into(), Bytes::<32>::new_for_test(), 1, version).sign(&alice);
+            let parent = state.get_blocks(0).pop().unwrap();
+            state.add_block(Block::new_child_for_test(Some(parent.header().unwrap()), author, vec![tx])).unwrap();
+        }
+        let chain = state.get_blocks(0);
+        // Same headers, but bodies whose signatures are broken.
+        let mut forged = chain.clone();
+        for block in forged[1..].iter_mut() {
+            block.txs[0].signature[1] ^= 1;
+        }
+
+        let chains = HashMap::from([
+            ("honest".to_string(), chain.clone()),
+            ("forger".to_string(), forged),
+        ]);
+        let mut node = Node::new(MemoryState::new(&genesis, ProofOfWork::default()), TestPeerClient::new(chains, usize::MAX), ProofOfWork::default(), Mempool::default());
+        node.syncer = Syncer::new(SyncConfig { bodies_per_request: 1, ..Default::default() });
+
+        assert_eq!(node.sync(&AtomicBool::new(false)), SyncStatus::CaughtUp { height: 4 });
+        assert_eq!(node.state.get_blocks(0), chain);
+        let reports = node.peer_client.reports.lock().unwrap();
+        assert!(!reports.is_empty());
+        assert!(reports.iter().all(|(peer_id, offense)| peer_id == "forger" && *offense == Offense::InvalidBlock));
+    }
+
+    #[test]
+    fn stuck_peer_test() {
+        let genesis = Genesis::default();
+        let chain = new_chain(&genesis, 6);
+        let state = MemoryState::new(&genesis, ProofOfWork::default());
+        for block in chain[1..4].iter() {
+            state.add_block(block.clone()).unwrap();
+        }
+        let mut peer_client = TestPeerClient::new(HashMap::from([("stuck".to_string(), chain.clone())]), 2);
+        peer_client.stuck.insert("stuck".to_string());
+        let node = Node::new(state, peer_client, ProofOfWork::default(), Mempool::default());
+
+        // Known headers again and again would keep the round going forever. The height
+        // the peer claimed is not trusted after that.
+        assert_eq!(node.sync(&AtomicBool::new(false)), SyncStatus::CaughtUp { height: 3 });
+        assert_eq!(*node.peer_client.reports.lock().unwrap(), [("stuck".to_string(), Offense::UnexpectedResponse)]);
+
+        // A stopped round leaves the download for later.
+        let peer_client = TestPeerClient::new(HashMap::from([("honest".to_string(), chain)]), 2);
+        let node = Node::new(MemoryState::new(&genesis, ProofOfWork::default()), peer_client, ProofOfWork::default(), Mempool::default());
+        assert_eq!(node.sync(&AtomicBool::new(true)), SyncStatus::Syncing { height: 0, target: 6 });
+    }
+
+    #[test]
+    fn parallel_download_test() {
+        let genesis = Genesis::default();
+        let chain = new_chain(&genesis, 9);
+        let chains = HashMap::from([
+            ("alice".to_string(), chain.clone()),
+            ("bob".to_string(), chain.clone()),
+        ]);
+        let mut node = Node::new(MemoryState::new(&genesis, ProofOfWork::default()), TestPeerClient::new(chains, usize::MAX), ProofOfWork::default(), Mempool::default());
+        node.syncer = Syncer::new(SyncConfig { headers_per_request: 6, bodies_per_request: 2, ..Default::default() });
+
+        assert_eq!(node.sync(&AtomicBool::new(false)), SyncStatus::CaughtUp { height: chain.len() as u64 - 1 });
+        assert_eq!(node.state.get_blocks(0), chain);
+        // The chunks went to both peers whichever sent the headers.
+        assert_eq!(*node.peer_client.served.lock().unwrap(), HashSet::from(["alice".to_string(), "bob".to_string()]));
+        assert!(node.peer_client.reports.lock().unwrap().is_empty());
+    }
+}
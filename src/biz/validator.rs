@@ -0,0 +1,141 @@
+//! Rules a block must satisfy before its txs are applied to the account state.
+
+use crate::consensus::params::ConsensusParams;
+use crate::error::Error;
+use crate::schema::v1::{Block, BlockHeader};
+use crate::utils;
+
+use super::consensus::Consensus;
+
+#[derive(Debug, Clone)]
+pub struct BlockValidator<C: Consensus> {
+    params: ConsensusParams,
+    consensus: C,
+}
+
+impl <C: Consensus>BlockValidator<C> {
+    pub fn new(params: ConsensusParams, consensus: C) -> Self {
+        BlockValidator { params, consensus }
+    }
+
+    pub fn params(&self) -> &ConsensusParams {
+        &self.params
+    }
+
+    /// Number of trailing ancestor headers `validate` needs.
+    pub fn lookback(&self) -> usize {
+        self.consensus.lookback()
+    }
+
+    /// Checks everything about `block` that does not depend on account balances
+    /// and versions. `ancestors` is ordered by height and ends with the current tip.
+    pub fn validate(&self, ancestors: &[BlockHeader], block: &Block) -> Result<(), Error> {
+        let header = block.header()?;
+        self.validate_header(ancestors, header)?;
+
+        let expected = block.compute_txs_root()?;
+        let actual = header.txs_root()?;
+        if actual != expected {
+            return Err(Error::InvalidTxsRoot { expected, actual });
+        }
+
+        let mut gas_used = 0u64;
+        for tx in block.txs.iter() {
+            tx.verify()?;
+            let raw_tx = tx.raw_tx()?;
+            self.params.validate_tx(raw_tx)?;
+            gas_used = gas_used.saturating_add(raw_tx.gas);
+        }
+        if gas_used > self.params.block_gas_limit {
+            return Err(Error::BlockGasLimitExceeded { limit: self.params.block_gas_limit, used: gas_used });
+        }
+        Ok(())
+    }
+
+    pub fn validate_header(&self, ancestors: &[BlockHeader], header: &BlockHeader) -> Result<(), Error> {
+        let parent = ancestors.last();
+
+        // Fields of the wrong length are refused before anything reads them.
+        header.author()?;
+        header.txs_root()?;
+
+        let expected = parent.map(|parent| parent.hash()).unwrap_or_default();
+        let actual = header.parent_hash()?;
+        if actual != expected {
+            return Err(Error::InvalidParentHash { expected, actual });
+        }
+
+        let expected = parent.map(|parent| parent.height + 1).unwrap_or_default();
+        if header.height != expected {
+            return Err(Error::InvalidBlockHeight { expected, actual: header.height });
+        }
+
+        self.consensus.verify_header(ancestors, header, utils::unix_timestamp())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wallet::Wallet;
+
+    use super::*;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::schema::v1::Tx;
+    use crate::types::Bytes;
+
+    #[test]
+    fn validate_test() {
+        let validator = BlockValidator::new(ConsensusParams::default(), ProofOfWork::default());
+        let miner = Wallet::new();
+
+        let genesis = Block::new_child_for_test(None, miner.address().into(), vec![]);
+        assert!(validator.validate(&[], &genesis).is_ok());
+        let ancestors = vec![genesis.header().unwrap().clone()];
+
+        let tx = Tx::new(miner.address().into(), Bytes::<32>::new_for_test(), 1, 1).sign(&miner);
+        let block = Block::new_child_for_test(ancestors.last(), miner.address().into(), vec![tx]);
+        assert!(validator.validate(&ancestors, &block).is_ok());
+
+        let mut invalid = block.clone();
+        invalid.header = None;
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::EmptyHeader)));
+
+        let mut invalid = block.clone();
+        invalid.header_mut().unwrap().parent_hash = Bytes::<32>::new_for_test().into();
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::InvalidParentHash { .. })));
+
+        for field in [0, 1, 2] {
+            let mut invalid = block.clone();
+            let header = invalid.header_mut().unwrap();
+            [&mut header.parent_hash, &mut header.author, &mut header.txs_root][field].truncate(31);
+            assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::InvalidBlockHeader)));
+        }
+        // Proof of work has no use for a seal.
+        let mut invalid = block.clone();
+        invalid.header_mut().unwrap().signature = vec![0; 65];
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::InvalidBlockHeader)));
+
+        let mut invalid = block.clone();
+        invalid.header_mut().unwrap().height = 2;
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::InvalidBlockHeight { expected: 1, actual: 2 })));
+
+        let mut invalid = block.clone();
+        invalid.header_mut().unwrap().timestamp = 0;
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::TimestampTooOld { .. })));
+
+        let mut invalid = block.clone();
+        invalid.txs.pop();
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::InvalidTxsRoot { .. })));
+
+        let mut invalid = block.clone();
+        invalid.txs[0].tx.as_mut().unwrap().sender = Bytes::<32>::new_for_test().into();
+        let txs_root = invalid.compute_txs_root().unwrap();
+        invalid.header_mut().unwrap().txs_root = txs_root.into();
+        assert!(matches!(validator.validate(&ancestors, &invalid), Err(Error::InvalidSigner { .. })));
+        let strict = BlockValidator::new(ConsensusParams { min_gas_price: 2, ..Default::default() }, ProofOfWork::default());
+        assert!(matches!(strict.validate(&ancestors, &block), Err(Error::GasPriceTooLow { min: 2, actual: 1 })));
+
+        let strict = BlockValidator::new(ConsensusParams { block_gas_limit: 20, ..Default::default() }, ProofOfWork::default());
+        assert!(matches!(strict.validate(&ancestors, &block), Err(Error::BlockGasLimitExceeded { limit: 20, used: 21 })));
+    }
+}
@@ -0,0 +1,45 @@
+//! Node configuration, loaded from a JSON file.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::biz::mempool::MempoolConfig;
+use crate::biz::producer::ProducerConfig;
+use crate::biz::sync::SyncConfig;
+use crate::error::Error;
+use crate::network::p2p::P2pConfig;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// The chain is only kept in memory when this is not set.
+    #[serde(default)]
+    pub data_dir: Option<PathBuf>,
+    pub genesis: PathBuf,
+    pub http_addr: SocketAddr,
+    /// Where the admin routes are served, localhost only by default.
+    #[serde(default = "default_admin_addr")]
+    pub admin_addr: SocketAddr,
+    #[serde(default)]
+    pub p2p: P2pConfig,
+    #[serde(default)]
+    pub mempool: MempoolConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Blocks are only produced when this is set.
+    #[serde(default)]
+    pub producer: Option<ProducerConfig>,
+}
+
+fn default_admin_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8081))
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
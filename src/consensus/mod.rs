@@ -0,0 +1,4 @@
+pub mod params;
+pub mod poa;
+pub mod pow;
+pub mod retarget;
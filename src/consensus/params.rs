@@ -0,0 +1,104 @@
+//! Economic and timing rules every node of a network has to agree on, set in the
+//! genesis spec.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::schema::v1::Tx;
+
+use super::retarget::RetargetParams;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsensusParams {
+    /// Coins minted for the author of a block, before any halving.
+    pub block_reward: u64,
+    /// Number of blocks after which the block reward halves, or 0 to never halve it.
+    pub halving_interval: u64,
+    /// Lowest gas price a tx may bid.
+    pub min_gas_price: u64,
+    /// Gas charged for a transfer.
+    pub transfer_gas: u64,
+    /// Most gas the txs of a single block may use.
+    pub block_gas_limit: u64,
+    /// Desired seconds between blocks.
+    pub target_block_time: u64,
+    /// Most best blocks a reorg may revert. Blocks further below the tip are final,
+    /// and side blocks that fall that far behind are dropped.
+    pub max_reorg_depth: u64,
+    pub retarget: RetargetParams,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        ConsensusParams {
+            block_reward: 25,
+            halving_interval: 0,
+            min_gas_price: 1,
+            transfer_gas: 21,
+            block_gas_limit: 21_000,
+            target_block_time: 10,
+            max_reorg_depth: 100,
+            retarget: RetargetParams::default(),
+        }
+    }
+}
+
+impl ConsensusParams {
+    /// Coins minted for the author of the block at `height`.
+    pub fn block_reward_at(&self, height: u64) -> u64 {
+        if self.halving_interval == 0 {
+            return self.block_reward;
+        }
+        let halvings = height / self.halving_interval;
+        self.block_reward.checked_shr(halvings.try_into().unwrap_or(u32::MAX)).unwrap_or_default()
+    }
+
+    /// Checks the gas of `tx` against these params.
+    pub fn validate_tx(&self, tx: &Tx) -> Result<(), Error> {
+        if tx.gas != self.transfer_gas {
+            return Err(Error::InvalidGas { expected: self.transfer_gas, actual: tx.gas });
+        }
+        if tx.gas_price < self.min_gas_price {
+            return Err(Error::GasPriceTooLow { min: self.min_gas_price, actual: tx.gas_price });
+        }
+        tx.total_cost()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Bytes;
+
+    #[test]
+    fn block_reward_test() {
+        let params = ConsensusParams { block_reward: 100, halving_interval: 10, ..Default::default() };
+        assert_eq!(params.block_reward_at(0), 100);
+        assert_eq!(params.block_reward_at(9), 100);
+        assert_eq!(params.block_reward_at(10), 50);
+        assert_eq!(params.block_reward_at(25), 25);
+        assert_eq!(params.block_reward_at(70), 0);
+        assert_eq!(params.block_reward_at(u64::MAX), 0);
+
+        assert_eq!(ConsensusParams::default().block_reward_at(u64::MAX), 25);
+    }
+
+    #[test]
+    fn validate_tx_test() {
+        let params = ConsensusParams { min_gas_price: 2, ..Default::default() };
+        let mut tx = Tx::new(Bytes::<32>::new_for_test(), Bytes::<32>::new_for_test(), 1, 1);
+        assert!(matches!(params.validate_tx(&tx), Err(Error::GasPriceTooLow { min: 2, actual: 1 })));
+
+        tx.gas_price = 2;
+        assert!(params.validate_tx(&tx).is_ok());
+
+        tx.gas = 1;
+        assert!(matches!(params.validate_tx(&tx), Err(Error::InvalidGas { expected: 21, actual: 1 })));
+
+        tx.gas = 21;
+        tx.gas_price = 878_416_384_462_359_601;
+        assert!(matches!(params.validate_tx(&tx), Err(Error::FeeOverflow)));
+    }
+}
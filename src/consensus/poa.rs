@@ -0,0 +1,188 @@
+//! Proof-of-authority over `BlockHeader::seal_hash()`.
+//!
+//! A fixed set of validators, listed in the genesis spec, take turns sealing blocks:
+//! the block at height `h` must be signed by `validators[h % validators.len()]`. Every
+//! block has difficulty 1, so the chain with the most work is simply the longest one,
+//! and blocks are at least `target_block_time` seconds apart.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use wallet::Wallet;
+
+use crate::biz::consensus::Consensus;
+use crate::error::Error;
+use crate::schema::v1::BlockHeader;
+use crate::types::Address;
+use crate::utils;
+
+use super::params::ConsensusParams;
+
+pub const DIFFICULTY: u64 = 1;
+
+#[derive(Debug, Clone)]
+pub struct ProofOfAuthority {
+    params: ConsensusParams,
+    validators: Vec<Address>,
+    /// Key of the local validator, if this node seals blocks.
+    signer: Option<Wallet>,
+}
+
+impl ProofOfAuthority {
+    pub fn new(params: ConsensusParams, validators: Vec<Address>, signer: Option<Wallet>) -> Result<Self, Error> {
+        if validators.is_empty() {
+            return Err(Error::EmptyValidatorSet);
+        }
+        Ok(ProofOfAuthority { params, validators, signer })
+    }
+
+    /// Validator whose turn it is to seal the block at `height`.
+    pub fn in_turn(&self, height: u64) -> Address {
+        self.validators[(height % self.validators.len() as u64) as usize]
+    }
+
+    pub fn recover_sealer(header: &BlockHeader) -> Result<Address, Error> {
+        let signature = header.signature().ok_or(Error::MissingSeal)?;
+        Ok(wallet::recover_address(&header.seal_hash(), &signature)?.into())
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    fn lookback(&self) -> usize {
+        1
+    }
+
+    fn prepare(&self, ancestors: &[BlockHeader], header: &mut BlockHeader) -> Result<(), Error> {
+        header.difficulty = DIFFICULTY;
+        if let Some(parent) = ancestors.last() {
+            header.timestamp = header.timestamp.max(parent.timestamp.saturating_add(self.params.target_block_time));
+        }
+        Ok(())
+    }
+
+    fn seal(&self, mut header: BlockHeader, cancel: &AtomicBool) -> Result<Option<BlockHeader>, Error> {
+        let Some(signer) = &self.signer else {
+            return Ok(None);
+        };
+        if Address::from(signer.address()) != self.in_turn(header.height) {
+            return Ok(None);
+        }
+
+        // Peers reject blocks from the future, so hold the block back until its slot.
+        while utils::unix_timestamp() < header.timestamp {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        header.signature = signer.sign(&header.seal_hash()).to_vec();
+        Ok(Some(header))
+    }
+
+    fn verify_header(&self, ancestors: &[BlockHeader], header: &BlockHeader, now: u64) -> Result<(), Error> {
+        if header.difficulty != DIFFICULTY {
+            return Err(Error::InvalidDifficulty { expected: DIFFICULTY, actual: header.difficulty });
+        }
+
+        if let Some(parent) = ancestors.last() {
+            let earliest = parent.timestamp.saturating_add(self.params.target_block_time);
+            if header.timestamp < earliest {
+                return Err(Error::BlockTooEarly { timestamp: header.timestamp, earliest });
+            }
+        }
+        let max = now.saturating_add(self.params.retarget.max_future_drift);
+        if header.timestamp > max {
+            return Err(Error::TimestampTooNew { timestamp: header.timestamp, max });
+        }
+
+        let expected = self.in_turn(header.height);
+        let actual = Self::recover_sealer(header)?;
+        if actual != expected {
+            return Err(Error::UnexpectedSealer { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Bytes;
+
+    fn new_header(parent: &BlockHeader) -> BlockHeader {
+        BlockHeader::new(parent.hash(), parent.height + 1, 0, Bytes::<32>::new_for_test(), Bytes::<32>::new_for_test())
+    }
+
+    #[test]
+    fn seal_test() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let validators = vec![alice.address().into(), bob.address().into()];
+        let params = ConsensusParams::default();
+        let verifier = ProofOfAuthority::new(params, validators.clone(), None).unwrap();
+        let alice_engine = ProofOfAuthority::new(params, validators.clone(), Some(alice)).unwrap();
+        let bob_engine = ProofOfAuthority::new(params, validators, Some(bob.clone())).unwrap();
+
+        let parent = BlockHeader { timestamp: utils::unix_timestamp() - params.target_block_time, ..Default::default() };
+        let ancestors = vec![parent.clone()];
+        let mut header = new_header(&parent);
+        bob_engine.prepare(&ancestors, &mut header).unwrap();
+        assert_eq!(header.difficulty, DIFFICULTY);
+        assert!(header.timestamp >= parent.timestamp + params.target_block_time);
+
+        let cancel = AtomicBool::new(false);
+        assert!(verifier.seal(header.clone(), &cancel).unwrap().is_none());
+        // Height 1 is bob's turn.
+        assert!(alice_engine.seal(header.clone(), &cancel).unwrap().is_none());
+        let sealed = bob_engine.seal(header.clone(), &cancel).unwrap().unwrap();
+        assert_eq!(ProofOfAuthority::recover_sealer(&sealed).unwrap(), Address::from(bob.address()));
+        assert!(verifier.verify_header(&ancestors, &sealed, utils::unix_timestamp()).is_ok());
+
+        assert!(matches!(verifier.verify_header(&ancestors, &header, utils::unix_timestamp()), Err(Error::MissingSeal)));
+    }
+
+    #[test]
+    fn verify_header_test() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let validators = vec![alice.address().into(), bob.address().into()];
+        let params = ConsensusParams::default();
+        let verifier = ProofOfAuthority::new(params, validators, None).unwrap();
+
+        let parent = BlockHeader { timestamp: 1_700_000_000, ..Default::default() };
+        let ancestors = vec![parent.clone()];
+        let mut header = new_header(&parent);
+        header.difficulty = DIFFICULTY;
+        header.timestamp = parent.timestamp + params.target_block_time;
+        let now = header.timestamp;
+
+        let mut sealed = header.clone();
+        sealed.signature = bob.sign(&sealed.seal_hash()).to_vec();
+        assert!(verifier.verify_header(&ancestors, &sealed, now).is_ok());
+
+        let mut invalid = header.clone();
+        invalid.signature = alice.sign(&invalid.seal_hash()).to_vec();
+        assert!(matches!(verifier.verify_header(&ancestors, &invalid, now), Err(Error::UnexpectedSealer { .. })));
+
+        let mut invalid = sealed.clone();
+        invalid.nonce += 1;
+        assert!(matches!(verifier.verify_header(&ancestors, &invalid, now), Err(Error::UnexpectedSealer { .. })));
+
+        let mut invalid = sealed.clone();
+        invalid.signature.pop();
+        assert!(matches!(verifier.verify_header(&ancestors, &invalid, now), Err(Error::MissingSeal)));
+
+        let mut invalid = sealed.clone();
+        invalid.difficulty = 2;
+        assert!(matches!(verifier.verify_header(&ancestors, &invalid, now), Err(Error::InvalidDifficulty { expected: 1, actual: 2 })));
+
+        let mut invalid = sealed.clone();
+        invalid.timestamp -= 1;
+        assert!(matches!(verifier.verify_header(&ancestors, &invalid, now), Err(Error::BlockTooEarly { .. })));
+
+        assert!(matches!(verifier.verify_header(&ancestors, &sealed, now - params.retarget.max_future_drift - 1), Err(Error::TimestampTooNew { .. })));
+
+        assert!(matches!(ProofOfAuthority::new(params, vec![], None), Err(Error::EmptyValidatorSet)));
+    }
+}
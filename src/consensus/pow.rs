@@ -0,0 +1,165 @@
+//! Proof-of-work over `BlockHeader::hash()`.
+//!
+//! A header with difficulty `d` is solved when the first 8 bytes of its hash, read as a
+//! big-endian `u64`, are no greater than `u64::MAX / d`. On average a solution takes
+//! `d` hashes, which also makes `d` the work a block contributes to its chain.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use log::debug;
+
+use crate::biz::consensus::Consensus;
+use crate::error::Error;
+use crate::schema::v1::BlockHeader;
+
+use super::params::ConsensusParams;
+use super::retarget;
+
+pub const MIN_DIFFICULTY: u64 = 1;
+
+pub fn target(difficulty: u64) -> u64 {
+    u64::MAX / difficulty.max(MIN_DIFFICULTY)
+}
+
+pub fn meets_target(header: &BlockHeader) -> bool {
+    let hash = header.hash();
+    let head = u64::from_be_bytes(hash[..8].try_into().unwrap());
+    head <= target(header.difficulty)
+}
+
+pub fn verify(header: &BlockHeader) -> Result<(), Error> {
+    if !meets_target(header) {
+        return Err(Error::InvalidProofOfWork { hash: header.hash(), difficulty: header.difficulty });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Miner {
+    threads: usize,
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Miner { threads: threads.max(1) }
+    }
+
+    /// Searches nonces on every thread until one of them solves the header or
+    /// `cancel` is set, in which case `None` is returned.
+    pub fn mine(&self, header: &BlockHeader, cancel: &AtomicBool) -> Option<BlockHeader> {
+        let solved = AtomicBool::new(false);
+        let solution = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    let mut header = header.clone();
+                    while !cancel.load(Ordering::Relaxed) && !solved.load(Ordering::Relaxed) {
+                        header.update_nonce_and_timestamp();
+                        if meets_target(&header) && !solved.swap(true, Ordering::Relaxed) {
+                            debug!("Solved block {} with nonce {}", header.height, header.nonce);
+                            *solution.lock().unwrap() = Some(header);
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        solution.into_inner().unwrap()
+    }
+}
+
+/// Consensus engine in which anyone may produce a block by solving its header at the
+/// difficulty set by `retarget`.
+#[derive(Debug, Clone)]
+pub struct ProofOfWork {
+    params: ConsensusParams,
+    miner: Miner,
+}
+
+impl ProofOfWork {
+    pub fn new(params: ConsensusParams, threads: usize) -> Self {
+        ProofOfWork { params, miner: Miner::new(threads) }
+    }
+}
+
+impl Default for ProofOfWork {
+    fn default() -> Self {
+        ProofOfWork::new(ConsensusParams::default(), 1)
+    }
+}
+
+impl Consensus for ProofOfWork {
+    fn lookback(&self) -> usize {
+        self.params.retarget.lookback()
+    }
+
+    fn prepare(&self, ancestors: &[BlockHeader], header: &mut BlockHeader) -> Result<(), Error> {
+        header.difficulty = retarget::next_difficulty(&self.params, ancestors);
+        Ok(())
+    }
+
+    fn seal(&self, header: BlockHeader, cancel: &AtomicBool) -> Result<Option<BlockHeader>, Error> {
+        Ok(self.miner.mine(&header, cancel))
+    }
+
+    fn verify_header(&self, ancestors: &[BlockHeader], header: &BlockHeader, now: u64) -> Result<(), Error> {
+        // Nobody signs a mined block.
+        if !header.signature.is_empty() {
+            return Err(Error::InvalidBlockHeader);
+        }
+        retarget::verify(&self.params, ancestors, header, now)?;
+        verify(header)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::types::Bytes;
+
+    fn new_header(difficulty: u64) -> BlockHeader {
+        let mut header = BlockHeader::new(
+            Bytes::<32>::new_for_test(),
+            1,
+            0,
+            Bytes::<32>::new_for_test(),
+            Bytes::<32>::new_for_test(),
+        );
+        header.difficulty = difficulty;
+        header
+    }
+
+    #[test]
+    fn mine_test() {
+        let header = new_header(1000);
+        let solved = Miner::new(2).mine(&header, &AtomicBool::new(false)).unwrap();
+
+        assert!(verify(&solved).is_ok());
+        assert_eq!(solved.parent_hash, header.parent_hash);
+        assert_eq!(solved.difficulty, header.difficulty);
+
+        let mut tampered = solved.clone();
+        tampered.difficulty = u64::MAX;
+        assert!(matches!(verify(&tampered), Err(Error::InvalidProofOfWork { .. })));
+    }
+
+    #[test]
+    fn mine_cancel_test() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let cancel = cancel.clone();
+            thread::spawn(move || Miner::new(2).mine(&new_header(u64::MAX), &cancel))
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, Ordering::Relaxed);
+        assert!(handle.join().unwrap().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+//! Difficulty retargeting shared by the miner and block validation.
+//!
+//! Every `interval` blocks the difficulty is scaled by how far the last interval drifted
+//! from `interval * target_block_time`. The measured timespan is clamped to a factor of
+//! `max_adjustment`, and header timestamps may not fall below the median-time-past of their
+//! ancestors, so a miner lying about time can only move the difficulty a bounded amount.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::schema::v1::BlockHeader;
+
+use super::params::ConsensusParams;
+use super::pow::MIN_DIFFICULTY;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetargetParams {
+    /// Number of blocks between two adjustments.
+    pub interval: u64,
+    /// Largest factor a single adjustment may raise or lower the difficulty by.
+    pub max_adjustment: u64,
+    /// Number of ancestors the median-time-past is taken over.
+    pub median_time_span: usize,
+    /// How many seconds a timestamp may run ahead of the local clock.
+    pub max_future_drift: u64,
+}
+
+impl Default for RetargetParams {
+    fn default() -> Self {
+        RetargetParams {
+            interval: 20,
+            max_adjustment: 4,
+            median_time_span: 11,
+            max_future_drift: 2 * 60 * 60,
+        }
+    }
+}
+
+impl RetargetParams {
+    /// Number of trailing ancestors the rules below look at.
+    pub fn lookback(&self) -> usize {
+        (self.interval as usize + 1).max(self.median_time_span)
+    }
+}
+
+/// Difficulty the child of the last header in `ancestors` must carry.
+///
+/// `ancestors` is ordered by height and ends with the parent; only the last
+/// `params.retarget.lookback()` entries are used.
+pub fn next_difficulty(params: &ConsensusParams, ancestors: &[BlockHeader]) -> u64 {
+    let Some(parent) = ancestors.last() else {
+        return MIN_DIFFICULTY;
+    };
+
+    let interval = params.retarget.interval.max(1) as usize;
+    if (parent.height + 1) % interval as u64 != 0 || ancestors.len() <= interval {
+        return parent.difficulty.max(MIN_DIFFICULTY);
+    }
+
+    let first = &ancestors[ancestors.len() - interval - 1];
+    let expected = interval as u64 * params.target_block_time.max(1);
+    let max_adjustment = params.retarget.max_adjustment.max(1);
+    let actual = parent.timestamp
+        .saturating_sub(first.timestamp)
+        .clamp(expected / max_adjustment, expected * max_adjustment)
+        .max(1);
+
+    let next = parent.difficulty as u128 * expected as u128 / actual as u128;
+    next.clamp(MIN_DIFFICULTY as u128, u64::MAX as u128) as u64
+}
+
+/// Median timestamp of the last `params.median_time_span` ancestors.
+pub fn median_time_past(params: &RetargetParams, ancestors: &[BlockHeader]) -> u64 {
+    let span = params.median_time_span.max(1).min(ancestors.len());
+    let mut timestamps: Vec<u64> = ancestors[ancestors.len() - span..]
+        .iter()
+        .map(|header| header.timestamp)
+        .collect();
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied().unwrap_or_default()
+}
+
+/// Checks the difficulty and timestamp of `header` against its ancestors and the local clock.
+pub fn verify(params: &ConsensusParams, ancestors: &[BlockHeader], header: &BlockHeader, now: u64) -> Result<(), Error> {
+    let expected = next_difficulty(params, ancestors);
+    if header.difficulty != expected {
+        return Err(Error::InvalidDifficulty { expected, actual: header.difficulty });
+    }
+
+    if !ancestors.is_empty() {
+        let median_time_past = median_time_past(&params.retarget, ancestors);
+        if header.timestamp < median_time_past {
+            return Err(Error::TimestampTooOld { timestamp: header.timestamp, median_time_past });
+        }
+    }
+
+    let max = now.saturating_add(params.retarget.max_future_drift);
+    if header.timestamp > max {
+        return Err(Error::TimestampTooNew { timestamp: header.timestamp, max });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Hash;
+
+    fn params() -> ConsensusParams {
+        ConsensusParams {
+            target_block_time: 10,
+            retarget: RetargetParams { interval: 10, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    fn genesis(difficulty: u64) -> BlockHeader {
+        let mut header = BlockHeader::new(Hash::default(), 0, 0, Default::default(), Hash::default());
+        header.timestamp = 1_000_000;
+        header.difficulty = difficulty;
+        header
+    }
+
+    /// Extends the chain by `blocks` headers, each taking the expected time to solve
+    /// with `hashrate` hashes per second.
+    fn simulate(chain: &mut Vec<BlockHeader>, blocks: u64, hashrate: u64) {
+        let params = params();
+        for _ in 0..blocks {
+            let parent = chain.last().unwrap();
+            let mut header = parent.clone();
+            header.height += 1;
+            header.parent_hash = parent.hash().into();
+            header.difficulty = next_difficulty(&params, chain);
+            header.timestamp = parent.timestamp + (header.difficulty / hashrate).max(1);
+            chain.push(header);
+        }
+    }
+
+    fn assert_near(actual: u64, expected: u64) {
+        let error = actual.abs_diff(expected) as f64 / expected as f64;
+        assert!(error < 0.1, "difficulty {actual} is not near {expected}");
+    }
+
+    #[test]
+    fn steady_hashrate_test() {
+        let mut chain = vec![genesis(1000)];
+        simulate(&mut chain, 100, 100);
+        assert!(chain.iter().all(|header| header.difficulty == 1000));
+    }
+
+    #[test]
+    fn hashrate_swing_test() {
+        let mut chain = vec![genesis(1000)];
+
+        simulate(&mut chain, 100, 300);
+        assert_near(chain.last().unwrap().difficulty, 3000);
+
+        simulate(&mut chain, 100, 50);
+        assert_near(chain.last().unwrap().difficulty, 500);
+    }
+
+    #[test]
+    fn clamped_adjustment_test() {
+        let mut chain = vec![genesis(1000)];
+        simulate(&mut chain, 100, 100);
+
+        // A hundredfold hashrate spike may only quadruple the difficulty per interval.
+        simulate(&mut chain, 10, 10_000);
+        assert_eq!(chain.last().unwrap().difficulty, 4000);
+    }
+
+    #[test]
+    fn verify_test() {
+        let params = params();
+        let mut chain = vec![genesis(1000)];
+        simulate(&mut chain, 20, 100);
+
+        let now = chain.last().unwrap().timestamp;
+        let mut header = chain.last().unwrap().clone();
+        header.height += 1;
+        header.timestamp = now + 10;
+        assert!(verify(&params, &chain, &header, now).is_ok());
+
+        header.difficulty = 1;
+        assert!(matches!(verify(&params, &chain, &header, now), Err(Error::InvalidDifficulty { expected: 1000, actual: 1 })));
+        header.difficulty = 1000;
+
+        header.timestamp = median_time_past(&params.retarget, &chain) - 1;
+        assert!(matches!(verify(&params, &chain, &header, now), Err(Error::TimestampTooOld { .. })));
+
+        header.timestamp = now + params.retarget.max_future_drift + 1;
+        assert!(matches!(verify(&params, &chain, &header, now), Err(Error::TimestampTooNew { .. })));
+    }
+}
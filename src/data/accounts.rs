@@ -0,0 +1,100 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::consensus::params::ConsensusParams;
+use crate::error::Error;
+use crate::schema::v1::{Block, SignedTx};
+use crate::types::Address;
+
+/// Balances and versions of every account, shared by the `State` backends.
+#[derive(Debug, Clone, Default)]
+pub struct Accounts {
+    pub balances: HashMap<Address, u64>,
+    pub versions: HashMap<Address, u64>,
+}
+
+/// Balances and versions the accounts touched by a block had before it was applied,
+/// `None` standing for an account that did not exist yet.
+#[derive(Debug, Clone, Default)]
+pub struct Undo {
+    balances: Vec<(Address, Option<u64>)>,
+    versions: Vec<(Address, Option<u64>)>,
+}
+
+impl Accounts {
+    pub fn balance(&self, account: &Address) -> u64 {
+        self.balances.get(account).copied().unwrap_or_default()
+    }
+
+    pub fn version(&self, account: &Address) -> u64 {
+        self.versions.get(account).copied().unwrap_or_default()
+    }
+
+    pub fn credit(&mut self, account: Address, amount: u64) -> Result<(), Error> {
+        let balance = self.balance(&account)
+            .checked_add(amount)
+            .ok_or(Error::BalanceOverflow(account))?;
+        self.balances.insert(account, balance);
+        Ok(())
+    }
+
+    pub fn debit(&mut self, account: Address, amount: u64) -> Result<(), Error> {
+        let balance = self.balance(&account)
+            .checked_sub(amount)
+            .ok_or(Error::InsufficientBalance(account))?;
+        self.balances.insert(account, balance);
+        Ok(())
+    }
+
+    pub fn apply_tx(&mut self, signed_tx: &SignedTx) -> Result<(), Error> {
+        let tx = signed_tx.raw_tx()?;
+        let sender = tx.sender()?;
+        let receiver = tx.receiver()?;
+
+        let expected = self.version(&sender) + 1;
+        if tx.version != expected {
+            return Err(Error::InvalidTxVersion { account: sender, expected, actual: tx.version });
+        }
+
+        self.debit(sender, tx.total_cost()?)?;
+        self.credit(receiver, tx.amount)?;
+        self.versions.insert(sender, expected);
+        Ok(())
+    }
+
+    /// Applies every tx of the block and pays the reward to its author, returning
+    /// what `revert` needs to take the block back out.
+    pub fn apply_block(&mut self, block: &Block, params: &ConsensusParams) -> Result<Undo, Error> {
+        let header = block.header()?;
+        let mut touched = BTreeSet::from([header.author()?]);
+        for tx in block.txs.iter() {
+            let tx = tx.raw_tx()?;
+            touched.insert(tx.sender()?);
+            touched.insert(tx.receiver()?);
+        }
+        let undo = Undo {
+            balances: touched.iter().map(|account| (*account, self.balances.get(account).copied())).collect(),
+            versions: touched.iter().map(|account| (*account, self.versions.get(account).copied())).collect(),
+        };
+
+        for tx in block.txs.iter() {
+            self.apply_tx(tx)?;
+        }
+        self.credit(header.author()?, block.block_reward(params)?)?;
+        Ok(undo)
+    }
+
+    pub fn revert(&mut self, undo: &Undo) {
+        for (account, balance) in undo.balances.iter() {
+            match balance {
+                Some(balance) => self.balances.insert(*account, *balance),
+                None => self.balances.remove(account),
+            };
+        }
+        for (account, version) in undo.versions.iter() {
+            match version {
+                Some(version) => self.versions.insert(*account, *version),
+                None => self.versions.remove(account),
+            };
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Append-only file of protobuf encoded blocks.
+//!
+//! Every record is `len (u32 LE) || checksum (4 bytes) || block`, where the checksum is
+//! the head of `utils::hash(block)`. A record only counts once it has been fully written
+//! and synced, so a crash part-way through an append leaves a torn tail that is cut off
+//! on the next open. A bad record with more data after it cannot come from a crash,
+//! so the log is left alone and opening it fails.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use log::warn;
+
+use crate::error::Error;
+use crate::schema::v1::Block;
+use crate::utils;
+
+const LEN_SIZE: usize = 4;
+
+const CHECKSUM_SIZE: usize = 4;
+
+const HEADER_SIZE: usize = LEN_SIZE + CHECKSUM_SIZE;
+
+#[derive(Debug)]
+pub struct BlockLog {
+    file: File,
+    len: u64,
+}
+
+impl BlockLog {
+    /// Opens the log at `path`, creating it if missing, and returns every intact block.
+    pub fn open(path: &Path) -> Result<(Self, Vec<Block>), Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let mut blocks = Vec::new();
+        let mut offset = 0usize;
+        while let Some((block, next)) = Self::read_record(&buf, offset) {
+            blocks.push(block);
+            offset = next;
+        }
+
+        if Self::record_end(&buf, offset).is_some_and(|end| end < buf.len()) {
+            return Err(Error::CorruptLog { offset: offset as u64 });
+        }
+        if offset < buf.len() {
+            warn!("Truncating torn tail of {} bytes from block log {}", buf.len() - offset, path.display());
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+
+        Ok((BlockLog { file, len: offset as u64 }, blocks))
+    }
+
+    /// Appends a block and syncs it to disk before returning.
+    pub fn append(&mut self, block: &Block) -> Result<(), Error> {
+        let payload: Vec<u8> = block.clone().into();
+        let checksum = utils::hash(&payload);
+
+        let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum[..CHECKSUM_SIZE]);
+        record.extend_from_slice(&payload);
+
+        if let Err(err) = self.file.write_all(&record).and_then(|_| self.file.sync_data()) {
+            // Drop whatever part of the record made it to the file.
+            self.file.set_len(self.len)?;
+            return Err(err.into());
+        }
+        self.len += record.len() as u64;
+        Ok(())
+    }
+
+    /// Where the record at `offset` ends according to its length, if the length is there.
+    fn record_end(buf: &[u8], offset: usize) -> Option<usize> {
+        let len = buf.get(offset..offset + LEN_SIZE)?;
+        Some(offset + HEADER_SIZE + u32::from_le_bytes(len.try_into().ok()?) as usize)
+    }
+
+    fn read_record(buf: &[u8], offset: usize) -> Option<(Block, usize)> {
+        let header = buf.get(offset..offset + HEADER_SIZE)?;
+        let start = offset + HEADER_SIZE;
+        let end = Self::record_end(buf, offset)?;
+        let payload = buf.get(start..end)?;
+
+        if utils::hash(payload)[..CHECKSUM_SIZE] != header[LEN_SIZE..] {
+            return None;
+        }
+        let block = Block::try_from(payload.to_vec()).ok()?;
+        Some((block, end))
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::biz::consensus::Consensus;
+use crate::biz::genesis::Genesis;
+use crate::biz::state::ChainUpdate;
+use crate::biz::validator::BlockValidator;
+use crate::error::Error;
+use crate::schema::v1::{Block, BlockHeader};
+use crate::types::{Address, Hash};
+
+use super::accounts::{Accounts, Undo};
+
+/// Blocks and the account state they lead to, shared by the `State` backends.
+///
+/// Blocks form a tree rooted at genesis. The best chain is the branch with the most
+/// cumulative difficulty and the only one applied to `accounts`; every other block is
+/// kept on a side branch in case its branch overtakes the best one later. The undo of
+/// every best block lets a reorg roll the accounts back to the common ancestor.
+///
+/// Reorgs go at most `ConsensusParams::max_reorg_depth` blocks deep. Blocks that
+/// would fork off further below the tip are refused, and side blocks are dropped
+/// once they fall that far behind, so side branches stay bounded.
+#[derive(Debug, Clone)]
+pub struct Chain<C: Consensus> {
+    validator: BlockValidator<C>,
+    blocks: Vec<BestBlock>,
+    index: HashMap<Hash, u64>,
+    side: HashMap<Hash, SideBlock>,
+    accounts: Accounts,
+}
+
+#[derive(Debug, Clone)]
+struct BestBlock {
+    hash: Hash,
+    block: Block,
+    total_work: u128,
+    undo: Undo,
+}
+
+#[derive(Debug, Clone)]
+struct SideBlock {
+    block: Block,
+    total_work: u128,
+}
+
+/// A checked block and what adding it does to the chain, see `Chain::prepare`.
+#[derive(Debug)]
+pub struct Prepared {
+    hash: Hash,
+    total_work: u128,
+    change: Change,
+}
+
+#[derive(Debug)]
+enum Change {
+    Extend { accounts: Accounts, undo: Undo },
+    Side,
+    Reorg { ancestor: u64, branch: Vec<(Hash, Undo)>, undo: Undo, accounts: Accounts },
+}
+
+impl <C: Consensus>Chain<C> {
+    /// Starts a chain holding only the genesis block and its allocations, whose blocks
+    /// are sealed by `consensus`.
+    pub fn new(genesis: &Genesis, consensus: C) -> Self {
+        let accounts = Accounts {
+            balances: genesis.alloc.iter().map(|(account, balance)| (*account, *balance)).collect(),
+            ..Default::default()
+        };
+        let genesis_block = BestBlock {
+            hash: genesis.hash(),
+            block: genesis.block(),
+            total_work: genesis.difficulty as u128,
+            undo: Undo::default(),
+        };
+        Chain {
+            validator: BlockValidator::new(genesis.consensus, consensus),
+            index: HashMap::from([(genesis_block.hash, 0)]),
+            blocks: vec![genesis_block],
+            side: HashMap::new(),
+            accounts,
+        }
+    }
+
+    /// Checks the block against its branch and works out what adding it does, leaving
+    /// the chain itself untouched. Txs are only checked against the accounts when the
+    /// block joins the best chain.
+    pub fn prepare(&self, block: &Block) -> Result<Prepared, Error> {
+        let header = block.header()?;
+        let hash = header.hash();
+        if self.has_block(&hash) {
+            return Err(Error::KnownBlock(hash));
+        }
+
+        let finalized = self.finalized_height();
+        if header.height <= finalized {
+            return Err(Error::BelowFinality { height: header.height, finalized });
+        }
+
+        let parent_hash = header.parent_hash()?;
+        self.validator.validate(&self.ancestors_of(&parent_hash)?, block)?;
+        let total_work = self.total_work_of(&parent_hash)? + header.difficulty as u128;
+
+        let tip = self.tip();
+        let change = if parent_hash == tip.hash {
+            let mut accounts = self.accounts.clone();
+            let undo = accounts.apply_block(block, self.validator.params())?;
+            Change::Extend { accounts, undo }
+        } else if total_work <= tip.total_work {
+            Change::Side
+        } else {
+            self.prepare_reorg(&parent_hash, block)?
+        };
+        Ok(Prepared { hash, total_work, change })
+    }
+
+    /// Rolls the accounts back to where the branch ending in `block` forks off the
+    /// best chain and replays the branch on top.
+    fn prepare_reorg(&self, parent_hash: &Hash, block: &Block) -> Result<Change, Error> {
+        let mut branch = vec![];
+        let mut cursor = *parent_hash;
+        while let Some(side) = self.side.get(&cursor) {
+            branch.push(cursor);
+            cursor = side.block.header()?.parent_hash()?;
+        }
+        branch.reverse();
+        // Parts of the branch may have been dropped already.
+        let ancestor = *self.index.get(&cursor).ok_or(Error::UnknownParent(cursor))?;
+
+        let mut accounts = self.accounts.clone();
+        for best in self.blocks[ancestor as usize + 1..].iter().rev() {
+            accounts.revert(&best.undo);
+        }
+        let branch = branch.into_iter()
+            .map(|hash| Ok((hash, accounts.apply_block(&self.side[&hash].block, self.validator.params())?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let undo = accounts.apply_block(block, self.validator.params())?;
+        Ok(Change::Reorg { ancestor, branch, undo, accounts })
+    }
+
+    pub fn commit(&mut self, block: Block, prepared: Prepared) -> ChainUpdate {
+        let Prepared { hash, total_work, change } = prepared;
+        match change {
+            Change::Extend { accounts, undo } => {
+                self.push_best(BestBlock { hash, block, total_work, undo });
+                self.accounts = accounts;
+                self.prune_side();
+                ChainUpdate::Extended
+            }
+            Change::Side => {
+                self.side.insert(hash, SideBlock { block, total_work });
+                ChainUpdate::SideBranch
+            }
+            Change::Reorg { ancestor, branch, undo, accounts } => {
+                let mut reverted = vec![];
+                for best in self.blocks.split_off(ancestor as usize + 1) {
+                    self.index.remove(&best.hash);
+                    self.side.insert(best.hash, SideBlock { block: best.block.clone(), total_work: best.total_work });
+                    reverted.push(best.block);
+                }
+
+                let mut applied = vec![];
+                for (hash, undo) in branch {
+                    if let Some(SideBlock { block, total_work }) = self.side.remove(&hash) {
+                        applied.push(block.clone());
+                        self.push_best(BestBlock { hash, block, total_work, undo });
+                    }
+                }
+                applied.push(block.clone());
+                self.push_best(BestBlock { hash, block, total_work, undo });
+                self.accounts = accounts;
+                self.prune_side();
+
+                info!("Reorganized from height {} onwards, reverting {} and applying {} blocks",
+                    ancestor + 1, reverted.len(), applied.len());
+                ChainUpdate::Reorganized { reverted, applied }
+            }
+        }
+    }
+
+    pub fn add_block(&mut self, block: Block) -> Result<ChainUpdate, Error> {
+        let prepared = self.prepare(&block)?;
+        Ok(self.commit(block, prepared))
+    }
+
+    fn push_best(&mut self, best: BestBlock) {
+        self.index.insert(best.hash, self.blocks.len() as u64);
+        self.blocks.push(best);
+    }
+
+    /// Height of the last block no reorg may revert.
+    fn finalized_height(&self) -> u64 {
+        self.block_height().saturating_sub(self.validator.params().max_reorg_depth)
+    }
+
+    /// Drops the side blocks at or below the finalized height, which no branch can
+    /// build on anymore.
+    fn prune_side(&mut self) {
+        let finalized = self.finalized_height();
+        self.side.retain(|_, side| side.block.header().is_ok_and(|header| header.height > finalized));
+    }
+
+    fn tip(&self) -> &BestBlock {
+        // The genesis block is never reverted, so there always is a tip.
+        self.blocks.last().unwrap()
+    }
+
+    fn total_work_of(&self, hash: &Hash) -> Result<u128, Error> {
+        match self.index.get(hash) {
+            Some(height) => Ok(self.blocks[*height as usize].total_work),
+            None => self.side.get(hash)
+                .map(|side| side.total_work)
+                .ok_or(Error::UnknownParent(*hash)),
+        }
+    }
+
+    /// Headers of the last blocks the validator looks back at on the branch ending
+    /// with `parent_hash`, ordered by height.
+    fn ancestors_of(&self, parent_hash: &Hash) -> Result<Vec<BlockHeader>, Error> {
+        let lookback = self.validator.lookback();
+        let mut headers = vec![];
+        let mut cursor = *parent_hash;
+        while headers.len() < lookback {
+            if let Some(height) = self.index.get(&cursor) {
+                let to = *height as usize + 1;
+                let from = to.saturating_sub(lookback - headers.len());
+                headers.extend(self.blocks[from..to].iter()
+                    .rev()
+                    .filter_map(|best| best.block.header().ok())
+                    .cloned());
+                break;
+            }
+            let side = self.side.get(&cursor).ok_or(Error::UnknownParent(*parent_hash))?;
+            let header = side.block.header()?;
+            headers.push(header.clone());
+            cursor = header.parent_hash()?;
+        }
+        headers.reverse();
+        Ok(headers)
+    }
+
+    /// Checks that `headers` extend the known block tree one after the other, as far
+    /// as headers alone can tell.
+    pub fn validate_headers(&self, headers: &[BlockHeader]) -> Result<(), Error> {
+        let Some(first) = headers.first() else {
+            return Ok(());
+        };
+        let mut ancestors = self.ancestors_of(&first.parent_hash()?)?;
+        // The parent is needed even by engines that look back at nothing else.
+        let lookback = self.validator.lookback().max(1);
+        for header in headers {
+            self.validator.validate_header(&ancestors, header)?;
+            ancestors.push(header.clone());
+            if ancestors.len() > lookback {
+                ancestors.remove(0);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_block(&self, hash: &Hash) -> bool {
+        self.index.contains_key(hash) || self.side.contains_key(hash)
+    }
+
+    pub fn block_height(&self) -> u64 {
+        self.blocks.len().saturating_sub(1) as u64
+    }
+
+    pub fn last_block_hash(&self) -> Option<Hash> {
+        self.blocks.last().map(|best| best.hash)
+    }
+
+    pub fn get_block(&self, height: u64) -> Result<Block, Error> {
+        self.blocks.get(height as usize)
+            .map(|best| best.block.clone())
+            .ok_or(Error::BlockNotFound(height))
+    }
+
+    pub fn get_blocks(&self, from_height: u64) -> Vec<Block> {
+        self.blocks.iter()
+            .skip(from_height as usize)
+            .map(|best| best.block.clone())
+            .collect()
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<Block> {
+        match self.index.get(hash) {
+            Some(height) => Some(self.blocks[*height as usize].block.clone()),
+            None => self.side.get(hash).map(|side| side.block.clone()),
+        }
+    }
+
+    pub fn get_headers(&self, from_height: u64, limit: usize) -> Vec<BlockHeader> {
+        self.blocks.iter()
+            .skip(from_height as usize)
+            .take(limit)
+            .filter_map(|best| best.block.header().ok())
+            .cloned()
+            .collect()
+    }
+
+    pub fn balance_of(&self, account: &Address) -> u64 {
+        self.accounts.balance(account)
+    }
+
+    pub fn version_of(&self, account: &Address) -> u64 {
+        self.accounts.version(account)
+    }
+
+    pub fn get_balances(&self) -> HashMap<Address, u64> {
+        self.accounts.balances.clone()
+    }
+}
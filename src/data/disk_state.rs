@@ -0,0 +1,268 @@
+//! `State` backend that keeps the chain in a data directory across restarts.
+//!
+//! The block log is the only thing written to disk; balances and versions are rebuilt
+//! by replaying it on open. A block is therefore either fully in the log and applied,
+//! or not there at all. Side branch blocks are logged too, so replaying the log in
+//! order makes the same fork choices again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+use log::info;
+
+use crate::biz::consensus::Consensus;
+use crate::biz::genesis::Genesis;
+use crate::biz::state::{ChainUpdate, State};
+use crate::error::Error;
+use crate::schema::v1::{Block, BlockHeader};
+use crate::types::{Address, Hash};
+
+use super::block_log::BlockLog;
+use super::chain::Chain;
+
+const BLOCK_LOG_FILE: &str = "blocks.log";
+
+#[derive(Debug, Clone)]
+pub struct DiskState<C: Consensus> {
+    chain: Arc<RwLock<Chain<C>>>,
+    log: Arc<Mutex<BlockLog>>,
+}
+
+impl <C: Consensus>DiskState<C> {
+    /// Opens the chain stored in `data_dir`, writing the genesis block on first use.
+    /// Fails with `Error::GenesisMismatch` if the stored chain started from another genesis.
+    pub fn open(data_dir: impl AsRef<Path>, genesis: &Genesis, consensus: C) -> Result<Self, Error> {
+        let data_dir = data_dir.as_ref().to_path_buf();
+        fs::create_dir_all(&data_dir)?;
+
+        let (mut log, blocks) = BlockLog::open(&data_dir.join(BLOCK_LOG_FILE))?;
+        let mut chain = Chain::new(genesis, consensus);
+        let mut blocks = blocks.into_iter();
+        match blocks.next() {
+            Some(stored) => {
+                let expected = genesis.hash();
+                let actual = stored.header()?.hash();
+                if actual != expected {
+                    return Err(Error::GenesisMismatch { expected, actual });
+                }
+            }
+            None => log.append(&genesis.block())?,
+        }
+        for block in blocks {
+            chain.add_block(block)?;
+        }
+        info!("Loaded {} blocks from {}", chain.get_blocks(0).len(), data_dir.display());
+
+        Ok(DiskState {
+            chain: Arc::new(RwLock::new(chain)),
+            log: Arc::new(Mutex::new(log)),
+        })
+    }
+}
+
+impl <C: Consensus>State for DiskState<C> {
+    fn block_height(&self) -> u64 {
+        self.chain.read().unwrap().block_height()
+    }
+
+    fn last_block_hash(&self) -> Option<Hash> {
+        self.chain.read().unwrap().last_block_hash()
+    }
+
+    fn add_block(&self, block: Block) -> Result<ChainUpdate, Error> {
+        // Holding the log lock serializes writers while readers keep using the chain.
+        let mut log = self.log.lock().unwrap();
+        let prepared = self.chain.read().unwrap().prepare(&block)?;
+        log.append(&block)?;
+        Ok(self.chain.write().unwrap().commit(block, prepared))
+    }
+
+    fn has_block(&self, hash: &Hash) -> bool {
+        self.chain.read().unwrap().has_block(hash)
+    }
+
+    fn get_block(&self, height: u64) -> Result<Block, Error> {
+        self.chain.read().unwrap().get_block(height)
+    }
+
+    fn get_blocks(&self, from_height: u64) -> Vec<Block> {
+        self.chain.read().unwrap().get_blocks(from_height)
+    }
+
+    fn get_block_by_hash(&self, hash: &Hash) -> Option<Block> {
+        self.chain.read().unwrap().get_block_by_hash(hash)
+    }
+
+    fn get_headers(&self, from_height: u64, limit: usize) -> Vec<BlockHeader> {
+        self.chain.read().unwrap().get_headers(from_height, limit)
+    }
+
+    fn validate_headers(&self, headers: &[BlockHeader]) -> Result<(), Error> {
+        self.chain.read().unwrap().validate_headers(headers)
+    }
+
+    fn balance_of(&self, account: &Address) -> u64 {
+        self.chain.read().unwrap().balance_of(account)
+    }
+
+    fn version_of(&self, account: &Address) -> u64 {
+        self.chain.read().unwrap().version_of(account)
+    }
+
+    fn get_balances(&self) -> HashMap<Address, u64> {
+        self.chain.read().unwrap().get_balances()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use wallet::Wallet;
+
+    use super::*;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::schema::v1::{SignedTx, Tx};
+    use crate::types::Bytes;
+    use crate::utils;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("atman-disk-state-{}", utils::gen_random_number::<u64>()))
+    }
+
+    fn new_block(state: &DiskState<ProofOfWork>, author: Address, txs: Vec<SignedTx>) -> Block {
+        let parent = state.get_blocks(0).pop();
+        Block::new_child_for_test(parent.as_ref().map(|block| block.header().unwrap()), author, txs)
+    }
+
+    #[test]
+    fn reopen_test() {
+        let data_dir = temp_dir();
+        let miner = Wallet::new();
+        let receiver = Bytes::<32>::new_for_test();
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        state.add_block(new_block(&state, miner.address().into(), vec![])).unwrap();
+        let tx = Tx::new(miner.address().into(), receiver, 4, 1).sign(&miner);
+        state.add_block(new_block(&state, miner.address().into(), vec![tx])).unwrap();
+        drop(state);
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        assert_eq!(state.block_height(), 2);
+        assert_eq!(state.balance_of(&receiver), 4);
+        assert_eq!(state.version_of(&miner.address().into()), 1);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn torn_tail_test() {
+        let data_dir = temp_dir();
+        let miner = Wallet::new();
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        state.add_block(new_block(&state, miner.address().into(), vec![])).unwrap();
+        let last_block_hash = state.last_block_hash();
+        let next_block: Vec<u8> = new_block(&state, miner.address().into(), vec![]).into();
+        drop(state);
+
+        // Simulate a crash part-way through appending the next record.
+        let log_path = data_dir.join(BLOCK_LOG_FILE);
+        let intact_len = fs::metadata(&log_path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        file.write_all(&(next_block.len() as u32).to_le_bytes()).unwrap();
+        file.write_all(&next_block[..next_block.len() / 2]).unwrap();
+        drop(file);
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        assert_eq!(state.block_height(), 1);
+        assert_eq!(state.last_block_hash(), last_block_hash);
+        assert_eq!(fs::metadata(&log_path).unwrap().len(), intact_len);
+
+        state.add_block(new_block(&state, miner.address().into(), vec![])).unwrap();
+        drop(state);
+        assert_eq!(DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap().block_height(), 2);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_record_test() {
+        let data_dir = temp_dir();
+        let miner = Wallet::new();
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        for _ in 0..3 {
+            state.add_block(new_block(&state, miner.address().into(), vec![])).unwrap();
+        }
+        drop(state);
+
+        // Flip a payload byte of the second record, with two more after it.
+        let log_path = data_dir.join(BLOCK_LOG_FILE);
+        let mut log = fs::read(&log_path).unwrap();
+        let offset = 8 + u32::from_le_bytes(log[..4].try_into().unwrap()) as usize;
+        log[offset + 8] ^= 1;
+        fs::write(&log_path, &log).unwrap();
+
+        let result = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default());
+        assert!(matches!(result, Err(Error::CorruptLog { offset: actual }) if actual == offset as u64));
+        // Nothing was cut off.
+        assert_eq!(fs::read(&log_path).unwrap(), log);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn rejected_block_is_not_persisted_test() {
+        let data_dir = temp_dir();
+        let miner = Wallet::new();
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        let mut block = new_block(&state, miner.address().into(), vec![]);
+        block.header_mut().unwrap().height = 3;
+        assert!(state.add_block(block).is_err());
+        drop(state);
+
+        assert_eq!(DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap().get_blocks(0).len(), 1);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn genesis_mismatch_test() {
+        let data_dir = temp_dir();
+        drop(DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap());
+
+        let other = Genesis { chain_id: "other".to_string(), ..Default::default() };
+        assert!(matches!(DiskState::open(&data_dir, &other, ProofOfWork::default()), Err(Error::GenesisMismatch { .. })));
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn reopen_after_reorg_test() {
+        let data_dir = temp_dir();
+        let genesis_header = Genesis::default().block().header.unwrap();
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        let a1 = Block::new_child_for_test(Some(&genesis_header), Bytes::<32>::new_for_test(), vec![]);
+        let b1 = Block::new_child_for_test(Some(&genesis_header), Bytes::<32>::new_for_test(), vec![]);
+        let b2 = Block::new_child_for_test(b1.header.as_ref(), Bytes::<32>::new_for_test(), vec![]);
+        state.add_block(a1.clone()).unwrap();
+        state.add_block(b1).unwrap();
+        state.add_block(b2).unwrap();
+        let last_block_hash = state.last_block_hash();
+        drop(state);
+
+        let state = DiskState::open(&data_dir, &Genesis::default(), ProofOfWork::default()).unwrap();
+        assert_eq!(state.block_height(), 2);
+        assert_eq!(state.last_block_hash(), last_block_hash);
+        assert!(state.has_block(&a1.header().unwrap().hash()));
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
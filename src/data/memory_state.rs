@@ -1,47 +1,284 @@
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
-use crate::biz::state::State;
+use crate::biz::consensus::Consensus;
+use crate::biz::genesis::Genesis;
+use crate::biz::state::{ChainUpdate, State};
 use crate::error::Error;
-use crate::schema::v1::Block;
-use crate::types::Hash;
+use crate::schema::v1::{Block, BlockHeader};
+use crate::types::{Address, Hash};
+
+use super::chain::Chain;
 
 #[derive(Debug, Clone)]
-pub struct MemoryState {}
+pub struct MemoryState<C: Consensus> {
+    inner: Arc<RwLock<Chain<C>>>,
+}
 
-impl State for MemoryState {
-    fn block_height(&self) -> u64 {
-        todo!()
+impl <C: Consensus>MemoryState<C> {
+    pub fn new(genesis: &Genesis, consensus: C) -> Self {
+        MemoryState { inner: Arc::new(RwLock::new(Chain::new(genesis, consensus))) }
     }
+}
 
-    fn version(&self) -> u64 {
-        todo!()
+impl <C: Consensus>State for MemoryState<C> {
+    fn block_height(&self) -> u64 {
+        self.inner.read().unwrap().block_height()
     }
 
     fn last_block_hash(&self) -> Option<Hash> {
-        todo!()
+        self.inner.read().unwrap().last_block_hash()
+    }
+
+    fn add_block(&self, block: Block) -> Result<ChainUpdate, Error> {
+        self.inner.write().unwrap().add_block(block)
+    }
+
+    fn has_block(&self, hash: &Hash) -> bool {
+        self.inner.read().unwrap().has_block(hash)
+    }
+
+    fn get_block(&self, height: u64) -> Result<Block, Error> {
+        self.inner.read().unwrap().get_block(height)
+    }
+
+    fn get_blocks(&self, from_height: u64) -> Vec<Block> {
+        self.inner.read().unwrap().get_blocks(from_height)
+    }
+
+    fn get_block_by_hash(&self, hash: &Hash) -> Option<Block> {
+        self.inner.read().unwrap().get_block_by_hash(hash)
+    }
+
+    fn get_headers(&self, from_height: u64, limit: usize) -> Vec<BlockHeader> {
+        self.inner.read().unwrap().get_headers(from_height, limit)
+    }
+
+    fn validate_headers(&self, headers: &[BlockHeader]) -> Result<(), Error> {
+        self.inner.read().unwrap().validate_headers(headers)
+    }
+
+    fn balance_of(&self, account: &Address) -> u64 {
+        self.inner.read().unwrap().balance_of(account)
+    }
+
+    fn version_of(&self, account: &Address) -> u64 {
+        self.inner.read().unwrap().version_of(account)
+    }
+
+    fn get_balances(&self) -> HashMap<Address, u64> {
+        self.inner.read().unwrap().get_balances()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wallet::Wallet;
+
+    use super::*;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::schema::v1::{SignedTx, Tx};
+    use crate::types::Bytes;
+
+    fn new_block(state: &MemoryState<ProofOfWork>, author: Address, txs: Vec<SignedTx>) -> Block {
+        let parent = state.get_blocks(0).pop();
+        Block::new_child_for_test(parent.as_ref().map(|block| block.header().unwrap()), author, txs)
     }
 
-    fn add_block(&self, block: Block) -> Result<(), Error> {
-        todo!()
+    #[test]
+    fn add_block_test() {
+        let state = MemoryState::new(&Genesis::default(), ProofOfWork::default());
+        let miner = Wallet::new();
+        let receiver = Bytes::<32>::new_for_test();
+
+        assert_eq!(state.block_height(), 0);
+        assert_eq!(state.last_block_hash(), Some(Genesis::default().hash()));
+
+        let block1 = new_block(&state, miner.address().into(), vec![]);
+        state.add_block(block1.clone()).unwrap();
+        assert_eq!(state.block_height(), 1);
+        assert_eq!(state.last_block_hash(), Some(block1.header().unwrap().hash()));
+        assert_eq!(state.balance_of(&miner.address().into()), 25);
+
+        let tx = Tx::new(miner.address().into(), receiver, 4, 1).sign(&miner);
+        let block2 = new_block(&state, miner.address().into(), vec![tx]);
+        state.add_block(block2).unwrap();
+
+        assert_eq!(state.block_height(), 2);
+        assert_eq!(state.balance_of(&miner.address().into()), 46);
+        assert_eq!(state.balance_of(&receiver), 4);
+        assert_eq!(state.version_of(&miner.address().into()), 1);
+        assert_eq!(state.version_of(&receiver), 0);
+        assert_eq!(state.get_balances().len(), 2);
+        assert!(matches!(state.get_block(3), Err(Error::BlockNotFound(3))));
     }
 
-    fn get_version(&self) -> HashMap<String, u64> {
-        todo!()
+    #[test]
+    fn add_invalid_block_test() {
+        let state = MemoryState::new(&Genesis::default(), ProofOfWork::default());
+        let miner = Wallet::new();
+        let receiver = Bytes::<32>::new_for_test();
+
+        state.add_block(new_block(&state, miner.address().into(), vec![])).unwrap();
+        let snapshot = state.get_balances();
+
+        let tx1 = Tx::new(miner.address().into(), receiver, 1, 1).sign(&miner);
+        let tx2 = Tx::new(miner.address().into(), receiver, 1, 3).sign(&miner);
+        let block = new_block(&state, miner.address().into(), vec![tx1.clone(), tx2]);
+        assert!(matches!(state.add_block(block), Err(Error::InvalidTxVersion { expected: 2, actual: 3, .. })));
+
+        let tx2 = Tx::new(miner.address().into(), receiver, 100, 2).sign(&miner);
+        let block = new_block(&state, miner.address().into(), vec![tx1, tx2]);
+        assert!(matches!(state.add_block(block), Err(Error::InsufficientBalance(_))));
+
+        let mut block = new_block(&state, miner.address().into(), vec![]);
+        block.header_mut().unwrap().height = 5;
+        assert!(matches!(state.add_block(block), Err(Error::InvalidBlockHeight { expected: 2, actual: 5 })));
+
+        let mut block = new_block(&state, miner.address().into(), vec![]);
+        block.header_mut().unwrap().parent_hash.truncate(31);
+        assert!(matches!(state.add_block(block), Err(Error::InvalidBlockHeader)));
+
+        assert_eq!(state.block_height(), 1);
+        assert_eq!(state.get_balances(), snapshot);
+        assert_eq!(state.version_of(&miner.address().into()), 0);
     }
 
-    fn get_block(&self, height: u64) -> Option<Block> {
-        todo!()
+    #[test]
+    fn genesis_alloc_test() {
+        let rich = Wallet::new();
+        let mut genesis = Genesis::default();
+        genesis.alloc.insert(rich.address().into(), 1000);
+
+        let state = MemoryState::new(&genesis, ProofOfWork::default());
+        assert_eq!(state.get_block(0).unwrap(), genesis.block());
+        assert_eq!(state.balance_of(&rich.address().into()), 1000);
+
+        let receiver = Bytes::<32>::new_for_test();
+        let tx = Tx::new(rich.address().into(), receiver, 500, 1).sign(&rich);
+        state.add_block(new_block(&state, Address::default(), vec![tx])).unwrap();
+        assert_eq!(state.balance_of(&rich.address().into()), 1000 - 500 - 21);
+        assert_eq!(state.balance_of(&receiver), 500);
     }
 
-    fn get_blocks(&self, from_height: u64) -> Vec<Option<Block>> {
-        todo!()
+    #[test]
+    fn reorg_test() {
+        let alice = Wallet::new();
+        let bob: Address = Bytes::<32>::new_for_test();
+        let miner_a: Address = Bytes::<32>::new_for_test();
+        let miner_b: Address = Bytes::<32>::new_for_test();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        let state = MemoryState::new(&genesis, ProofOfWork::default());
+        let genesis_header = genesis.block().header.unwrap();
+
+        let tx = Tx::new(alice.address().into(), bob, 10, 1).sign(&alice);
+        let a1 = Block::new_child_for_test(Some(&genesis_header), miner_a, vec![tx]);
+        assert_eq!(state.add_block(a1.clone()).unwrap(), ChainUpdate::Extended);
+        assert_eq!(state.balance_of(&bob), 10);
+
+        // A competing block with the same work stays on a side branch.
+        let b1 = Block::new_child_for_test(Some(&genesis_header), miner_b, vec![]);
+        assert_eq!(state.add_block(b1.clone()).unwrap(), ChainUpdate::SideBranch);
+        assert_eq!(state.last_block_hash(), Some(a1.header().unwrap().hash()));
+        assert!(matches!(state.add_block(b1.clone()), Err(Error::KnownBlock(_))));
+
+        // Once its branch has more work it becomes the best chain.
+        let b2 = Block::new_child_for_test(b1.header.as_ref(), miner_b, vec![]);
+        assert_eq!(
+            state.add_block(b2.clone()).unwrap(),
+            ChainUpdate::Reorganized { reverted: vec![a1.clone()], applied: vec![b1.clone(), b2.clone()] }
+        );
+        assert_eq!(state.block_height(), 2);
+        assert_eq!(state.get_blocks(1), vec![b1, b2.clone()]);
+        assert_eq!(state.balance_of(&alice.address().into()), 1000);
+        assert_eq!(state.version_of(&alice.address().into()), 0);
+        assert_eq!(state.balance_of(&bob), 0);
+        assert_eq!(state.balance_of(&miner_a), 0);
+        assert_eq!(state.balance_of(&miner_b), 50);
+
+        // And the old branch can take over again.
+        let a2 = Block::new_child_for_test(a1.header.as_ref(), miner_a, vec![]);
+        assert_eq!(state.add_block(a2.clone()).unwrap(), ChainUpdate::SideBranch);
+        let a3 = Block::new_child_for_test(a2.header.as_ref(), miner_a, vec![]);
+        assert!(matches!(state.add_block(a3).unwrap(), ChainUpdate::Reorganized { .. }));
+        assert_eq!(state.block_height(), 3);
+        assert_eq!(state.version_of(&alice.address().into()), 1);
+        assert_eq!(state.balance_of(&bob), 10);
+        assert_eq!(state.balance_of(&miner_b), 0);
+
+        let orphan = Block::new_child_for_test(b2.header.as_ref().map(|header| {
+            let mut header = header.clone();
+            header.nonce += 1;
+            header
+        }).as_ref(), miner_b, vec![]);
+        assert!(matches!(state.add_block(orphan), Err(Error::UnknownParent(_))));
     }
 
-    fn get_balance(&self) -> u64 {
-        todo!()
+    #[test]
+    fn finality_test() {
+        let mut genesis = Genesis::default();
+        genesis.consensus.max_reorg_depth = 2;
+        let state = MemoryState::new(&genesis, ProofOfWork::default());
+        let miner: Address = Bytes::<32>::new_for_test();
+        let blocks: Vec<Block> = (0..4)
+            .map(|_| {
+                let block = new_block(&state, miner, vec![]);
+                state.add_block(block.clone()).unwrap();
+                block
+            })
+            .collect();
+        let [a1, a2, a3, a4] = &blocks[..] else { unreachable!() };
+        let other: Address = Bytes::<32>::new_for_test();
+
+        // Forking off at height 2 reverts 2 blocks, at height 1 it would revert 3.
+        let b2 = Block::new_child_for_test(a1.header.as_ref(), other, vec![]);
+        assert!(matches!(state.add_block(b2), Err(Error::BelowFinality { height: 2, finalized: 2 })));
+        let b3 = Block::new_child_for_test(a2.header.as_ref(), other, vec![]);
+        assert_eq!(state.add_block(b3.clone()).unwrap(), ChainUpdate::SideBranch);
+        let b4 = Block::new_child_for_test(b3.header.as_ref(), other, vec![]);
+        assert_eq!(state.add_block(b4.clone()).unwrap(), ChainUpdate::SideBranch);
+        let b5 = Block::new_child_for_test(b4.header.as_ref(), other, vec![]);
+        assert_eq!(
+            state.add_block(b5.clone()).unwrap(),
+            ChainUpdate::Reorganized { reverted: vec![a3.clone(), a4.clone()], applied: vec![b3, b4, b5] }
+        );
+
+        // Height 3 is final now, so the reverted block there is dropped.
+        assert!(!state.has_block(&a3.header().unwrap().hash()));
+        assert!(state.has_block(&a4.header().unwrap().hash()));
+        let a4_fork = Block::new_child_for_test(a3.header.as_ref(), other, vec![]);
+        assert!(matches!(state.add_block(a4_fork), Err(Error::UnknownParent(_))));
     }
 
-    fn get_balances(&self) -> HashMap<String, u64> {
-        todo!()
+    #[test]
+    fn headers_test() {
+        let state = MemoryState::new(&Genesis::default(), ProofOfWork::default());
+        let miner: Address = Bytes::<32>::new_for_test();
+        let b1 = new_block(&state, miner, vec![]);
+        state.add_block(b1.clone()).unwrap();
+        let b2 = new_block(&state, miner, vec![]);
+        state.add_block(b2.clone()).unwrap();
+        let side = Block::new_child_for_test(b1.header.as_ref(), Bytes::<32>::new_for_test(), vec![]);
+        state.add_block(side.clone()).unwrap();
+
+        assert_eq!(state.get_headers(1, 10), vec![b1.header.clone().unwrap(), b2.header.clone().unwrap()]);
+        assert_eq!(state.get_headers(1, 1).len(), 1);
+        assert!(state.get_headers(3, 10).is_empty());
+        assert_eq!(state.get_block_by_hash(&side.header().unwrap().hash()), Some(side.clone()));
+        assert_eq!(state.get_block_by_hash(&Bytes::<32>::new_for_test()), None);
+
+        // Headers may extend any branch, one after the other.
+        let b3 = Block::new_child_for_test(b2.header.as_ref(), miner, vec![]);
+        let b4 = Block::new_child_for_test(b3.header.as_ref(), miner, vec![]);
+        assert!(state.validate_headers(&[b3.header.clone().unwrap(), b4.header.clone().unwrap()]).is_ok());
+        let fork = Block::new_child_for_test(side.header.as_ref(), miner, vec![]);
+        assert!(state.validate_headers(&[fork.header.clone().unwrap()]).is_ok());
+        assert!(matches!(state.validate_headers(&[b4.header.clone().unwrap()]), Err(Error::UnknownParent(_))));
+        assert!(matches!(
+            state.validate_headers(&[b3.header.clone().unwrap(), fork.header.unwrap()]),
+            Err(Error::InvalidParentHash { .. })
+        ));
+        // Nothing was added.
+        assert_eq!(state.block_height(), 2);
     }
-}
\ No newline at end of file
+}
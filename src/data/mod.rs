@@ -1 +1,5 @@
-mod memory_state;
\ No newline at end of file
+pub mod memory_state;
+pub mod disk_state;
+mod accounts;
+mod block_log;
+mod chain;
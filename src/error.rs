@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::types::{Address, Hash};
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Failed to decode hash from hex")]
@@ -8,12 +10,242 @@ pub enum Error {
     #[error("Empty raw transaction")]
     EmptyRawTx,
 
+    #[error("Malformed transaction")]
+    InvalidTx,
+
     #[error("Empty header")]
     EmptyHeader,
 
+    #[error("Malformed block header")]
+    InvalidBlockHeader,
+
+    #[error("Block {0} not found")]
+    BlockNotFound(u64),
+
+    #[error("Block {0} is already known")]
+    KnownBlock(Hash),
+
+    #[error("Parent {0} of the block is unknown")]
+    UnknownParent(Hash),
+
+    #[error("Block at height {height} forks off the final chain at height {finalized}")]
+    BelowFinality {
+        height: u64,
+        finalized: u64,
+    },
+
     #[error(transparent)]
     InvalidP2pMessage(#[from] prost::DecodeError),
 
+    #[error("Invalid request")]
+    InvalidRequest,
+
     #[error("Invalid response")]
     InvalidResponse,
+
+    #[error("Unknown peer {0}")]
+    UnknownPeer(String),
+
+    #[error("Peer {peer_id} failed to handle the request: {message}")]
+    PeerFailed {
+        peer_id: String,
+        message: String,
+    },
+
+    #[error("Request {id} to {peer_id} timed out")]
+    RequestTimeout {
+        peer_id: String,
+        id: u64,
+    },
+
+    #[error("Connection to {0} is closed")]
+    ConnectionClosed(String),
+
+    #[error("Handshake required before any other request")]
+    HandshakeRequired,
+
+    #[error("Peer is on chain {actual}, expected {expected}")]
+    ChainIdMismatch {
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Peer started from genesis {actual}, expected {expected}")]
+    PeerGenesisMismatch {
+        expected: Hash,
+        actual: Hash,
+    },
+
+    #[error("Peer speaks protocol version {actual}, the oldest supported is {min}")]
+    UnsupportedProtocolVersion {
+        min: u32,
+        actual: u32,
+    },
+
+    #[error("Peer identity {actual} does not match {expected}")]
+    PeerIdentityMismatch {
+        expected: Address,
+        actual: Address,
+    },
+
+    #[error("Invalid node key")]
+    InvalidNodeKey,
+
+    #[error("Peer {0} is banned")]
+    PeerBanned(String),
+
+    #[error("Connected to self")]
+    SelfConnection,
+
+    #[error("Sync worker panicked")]
+    SyncWorkerPanicked,
+
+    #[error(transparent)]
+    P2p(#[from] p2p::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(#[from] wallet::Error),
+
+    #[error("Signer {signer} does not match sender {sender}")]
+    InvalidSigner {
+        sender: Address,
+        signer: Address,
+    },
+
+    #[error("Invalid parent hash, expected {expected}, got {actual}")]
+    InvalidParentHash {
+        expected: Hash,
+        actual: Hash,
+    },
+
+    #[error("Invalid txs root, expected {expected}, got {actual}")]
+    InvalidTxsRoot {
+        expected: Hash,
+        actual: Hash,
+    },
+
+    #[error("Invalid block height, expected {expected}, got {actual}")]
+    InvalidBlockHeight {
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Invalid gas {actual}, expected {expected}")]
+    InvalidGas {
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Gas price {actual} is below the minimum of {min}")]
+    GasPriceTooLow {
+        min: u64,
+        actual: u64,
+    },
+
+    #[error("Block uses {used} gas, over the limit of {limit}")]
+    BlockGasLimitExceeded {
+        limit: u64,
+        used: u64,
+    },
+
+    #[error("Invalid version of {account}, expected {expected}, got {actual}")]
+    InvalidTxVersion {
+        account: Address,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Fees overflow")]
+    FeeOverflow,
+
+    #[error("Insufficient balance of {0}")]
+    InsufficientBalance(Address),
+
+    #[error("Balance overflow of {0}")]
+    BalanceOverflow(Address),
+
+    #[error("Hash {hash} does not meet difficulty {difficulty}")]
+    InvalidProofOfWork {
+        hash: Hash,
+        difficulty: u64,
+    },
+
+    #[error("Invalid difficulty, expected {expected}, got {actual}")]
+    InvalidDifficulty {
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Timestamp {timestamp} is before median time past {median_time_past}")]
+    TimestampTooOld {
+        timestamp: u64,
+        median_time_past: u64,
+    },
+
+    #[error("Timestamp {timestamp} is later than {max}")]
+    TimestampTooNew {
+        timestamp: u64,
+        max: u64,
+    },
+
+    #[error("Timestamp {timestamp} is before the earliest allowed {earliest}")]
+    BlockTooEarly {
+        timestamp: u64,
+        earliest: u64,
+    },
+
+    #[error("Block is not sealed")]
+    MissingSeal,
+
+    #[error("Block sealed by {actual}, expected validator {expected}")]
+    UnexpectedSealer {
+        expected: Address,
+        actual: Address,
+    },
+
+    #[error("Validator set is empty")]
+    EmptyValidatorSet,
+
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Block log is corrupt at offset {offset}")]
+    CorruptLog {
+        offset: u64,
+    },
+
+    #[error("Genesis mismatch, configured {expected}, stored {actual}")]
+    GenesisMismatch {
+        expected: Hash,
+        actual: Hash,
+    },
+
+    #[error("Tx {0} is already in the mempool")]
+    DuplicateTx(Hash),
+
+    #[error("Stale version of {account}, current {current}, got {actual}")]
+    StaleTxVersion {
+        account: Address,
+        current: u64,
+        actual: u64,
+    },
+
+    #[error("Replacement of version {version} of {account} needs a gas price of at least {min_gas_price}")]
+    ReplacementUnderpriced {
+        account: Address,
+        version: u64,
+        min_gas_price: u64,
+    },
+
+    #[error("Mempool is full")]
+    MempoolFull,
+
+    #[error("Tx {id} with timestamp {timestamp} has expired")]
+    TxExpired {
+        id: Hash,
+        timestamp: u64,
+    },
 }
\ No newline at end of file
@@ -1,3 +1,17 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, thread};
+
+use log::{error, info};
+
+use biz::{consensus::Consensus, genesis::{EngineSpec, Genesis}, mempool::Mempool, producer::BlockProducer, state::State, sync::Syncer, Node};
+use config::Config;
+use consensus::{poa::ProofOfAuthority, pow::ProofOfWork};
+use data::{disk_state::DiskState, memory_state::MemoryState};
+use network::p2p::{address_book::{AddressBook, ADDRESS_BOOK_FILE}, handshake::LocalNode, P2pClient};
+use p2p::NodeKey;
+use wallet::Wallet;
+
 mod schema;
 mod types;
 mod network;
@@ -5,7 +19,110 @@ mod biz;
 mod error;
 mod data;
 mod utils;
+mod consensus;
+mod config;
+
+const DEFAULT_CONFIG_FILE: &str = "config.json";
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let config_path = env::args().nth(1).unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string());
+    if let Err(err) = run(&config_path).await {
+        error!("{err}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(config_path: &str) -> Result<(), error::Error> {
+    let config = Config::load(config_path)?;
+    let genesis = Genesis::load(&config.genesis)?;
+    info!("Starting node of chain {} with genesis {}", genesis.chain_id, genesis.hash());
+
+    match &genesis.engine {
+        EngineSpec::ProofOfWork => {
+            let threads = config.producer.as_ref().map_or(1, |producer| producer.threads);
+            let consensus = ProofOfWork::new(genesis.consensus, threads);
+            open_state(config, genesis, consensus).await
+        }
+        EngineSpec::ProofOfAuthority { validators } => {
+            let signer = match config.producer.as_ref().and_then(|producer| producer.signer.as_ref()) {
+                Some(secret_key) => Some(Wallet::from_hex(secret_key)?),
+                None => None,
+            };
+            let consensus = ProofOfAuthority::new(genesis.consensus, validators.clone(), signer)?;
+            open_state(config, genesis, consensus).await
+        }
+    }
+}
+
+async fn open_state<C: Consensus>(config: Config, genesis: Genesis, consensus: C) -> Result<(), error::Error> {
+    match &config.data_dir {
+        Some(data_dir) => {
+            let state = DiskState::open(data_dir, &genesis, consensus.clone())?;
+            serve(config, genesis, state, consensus).await
+        }
+        None => {
+            let state = MemoryState::new(&genesis, consensus.clone());
+            serve(config, genesis, state, consensus).await
+        }
+    }
+}
+
+async fn serve<S: State, C: Consensus>(config: Config, genesis: Genesis, state: S, consensus: C) -> Result<(), error::Error> {
+    let node_key = match &config.p2p.node_key {
+        Some(node_key) => {
+            let secret = hex::decode(node_key.strip_prefix("0x").unwrap_or(node_key))?;
+            NodeKey::from_secret(secret.try_into().map_err(|_| error::Error::InvalidNodeKey)?)
+        }
+        None => NodeKey::generate(),
+    };
+    let local = LocalNode::new(&genesis, node_key);
+    info!("Node id is {}", local.node_id);
+    let book = match &config.data_dir {
+        Some(data_dir) => AddressBook::open(data_dir.join(ADDRESS_BOOK_FILE))?,
+        None => AddressBook::default(),
+    };
+
+    let node = Node {
+        syncer: Syncer::new(config.sync),
+        ..Node::new(
+            state.clone(),
+            P2pClient::new(&config.p2p, local.clone(), state, book),
+            consensus,
+            Mempool::new(config.mempool, genesis.consensus),
+        )
+    };
+    network::p2p::server::start(config.p2p.listen_addr, local, node.clone())?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let peer_client = node.peer_client.clone();
+        let stop = stop.clone();
+        thread::spawn(move || peer_client.run_discovery(&stop));
+    }
+    {
+        let node = node.clone();
+        let stop = stop.clone();
+        thread::spawn(move || node.run_sync(&stop));
+    }
+    let producer = config.producer.map(|producer| {
+        let producer = BlockProducer::new(producer, genesis.consensus);
+        let node = node.clone();
+        let stop = stop.clone();
+        thread::spawn(move || node.run_producer(&producer, &stop))
+    });
+
+    tokio::select! {
+        _ = network::http::run(config.http_addr, node.clone()) => {}
+        _ = network::http::run_admin(config.admin_addr, node) => {}
+        _ = tokio::signal::ctrl_c() => info!("Shutting down"),
+    }
 
-fn main() {
-    println!("Hello, world!");
+    stop.store(true, Ordering::Relaxed);
+    if let Some(producer) = producer {
+        let _ = producer.join();
+    }
+    Ok(())
 }
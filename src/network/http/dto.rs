@@ -2,9 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::{schema, types::{Address, Hash, Signature}};
+use crate::{error::Error, schema, types::{Address, Hash, Signature}};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Tx{
     pub from: Address,
     pub to: Address,
@@ -15,7 +15,7 @@ pub struct Tx{
     pub timestamp: u64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct  SignedTx {
     pub tx: Tx,
     pub signature: Signature,
@@ -29,6 +29,9 @@ pub struct BlockHeader {
     timestamp: u64,
     author: Address,
     txs_root: Hash,
+    difficulty: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<Signature>,
 }
 
 #[derive(Debug, Serialize)]
@@ -53,56 +56,94 @@ pub struct VersionReq {
     pub account: Address,
 }
 
-//TODO(Haydn): Use TxID to identify a transaction
 #[derive(Debug, Serialize, Deserialize)]
-pub struct TxReq {
-    pub from: Address,
-    pub to: Address,
+pub struct VersionResp {
+    pub account: Address,
     pub version: u64,
-    pub amount: u64,
 }
 
-impl From<schema::v1::Tx> for Tx {
-    fn from(tx: schema::v1::Tx) -> Self {
-        Tx { 
-            from: tx.receiver.into(), 
-            to: tx.sender.into(), 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TxResp {
+    pub id: Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BanReq {
+    /// Seconds to ban the peer for, the configured ban duration if not set.
+    pub duration: Option<u64>,
+}
+
+impl TryFrom<schema::v1::Tx> for Tx {
+    type Error = Error;
+
+    fn try_from(tx: schema::v1::Tx) -> Result<Self, Self::Error> {
+        Ok(Tx { 
+            from: tx.sender()?, 
+            to: tx.receiver()?, 
             amount: tx.amount,
             version: tx.version, 
             gas: tx.gas, 
             gas_price: tx.gas_price, 
             timestamp: tx.timestamp, 
-        }
+        })
     }
 }
 
-impl From<schema::v1::SignedTx> for SignedTx {
-    fn from(signed_tx: schema::v1::SignedTx) -> Self {
-        SignedTx { 
-            tx: signed_tx.tx.unwrap().into(), 
-            signature: Signature::from(signed_tx.signature),
+impl TryFrom<schema::v1::SignedTx> for SignedTx {
+    type Error = Error;
+
+    fn try_from(signed_tx: schema::v1::SignedTx) -> Result<Self, Self::Error> {
+        Ok(SignedTx { 
+            tx: signed_tx.raw_tx()?.clone().try_into()?, 
+            signature: signed_tx.signature()?,
+        })
+    }
+}
+
+impl From<Tx> for schema::v1::Tx {
+    fn from(tx: Tx) -> Self {
+        schema::v1::Tx {
+            sender: tx.from.into(),
+            receiver: tx.to.into(),
+            amount: tx.amount,
+            version: tx.version,
+            gas: tx.gas,
+            gas_price: tx.gas_price,
+            timestamp: tx.timestamp,
         }
     }
 }
 
-impl From<schema::v1::BlockHeader> for BlockHeader {
-    fn from(header: schema::v1::BlockHeader) -> Self {
-        BlockHeader {
-            parent_hash: header.parent_hash.into(),
+impl From<SignedTx> for schema::v1::SignedTx {
+    fn from(signed_tx: SignedTx) -> Self {
+        schema::v1::SignedTx::new(Some(signed_tx.tx.into()), signed_tx.signature)
+    }
+}
+
+impl TryFrom<schema::v1::BlockHeader> for BlockHeader {
+    type Error = Error;
+
+    fn try_from(header: schema::v1::BlockHeader) -> Result<Self, Self::Error> {
+        Ok(BlockHeader {
+            parent_hash: header.parent_hash()?,
             height: header.height,
             nonce: header.nonce,
             timestamp: header.timestamp,
-            author: header.author.into(),
-            txs_root: header.txs_root.into(),
-        }
+            author: header.author()?,
+            txs_root: header.txs_root()?,
+            difficulty: header.difficulty,
+            signature: header.signature(),
+        })
     }
 }
 
-impl From<schema::v1::Block> for Block {
-    fn from(block: schema::v1::Block) -> Self {
-        Block { 
-            header: block.header.unwrap().into(), 
-            txs: block.txs.into_iter().map(|tx|tx.into()).collect(), 
-        }
+impl TryFrom<schema::v1::Block> for Block {
+    type Error = Error;
+
+    fn try_from(block: schema::v1::Block) -> Result<Self, Self::Error> {
+        Ok(Block { 
+            header: block.header()?.clone().try_into()?, 
+            txs: block.txs.into_iter().map(SignedTx::try_from).collect::<Result<_, _>>()?, 
+        })
     }
 }
@@ -1,6 +1,7 @@
 //! HTTP server that handles requests from the outside world.
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use axum::{
     extract::{Extension, Path, Query}, 
@@ -11,67 +12,130 @@ use axum::{
     Router, 
     Server
 };
-use dto::{GetBlocksReq, TxReq, VersionReq};
+use dto::{BanReq, Block, BlockResp, GetBlocksReq, SignedTx, TxResp, VersionReq, VersionResp};
 use log::info;
 
-use crate::biz::{peer_client::PeerClient, state::State, Node};
+use crate::biz::{consensus::Consensus, peer_client::PeerClient, state::State, Node};
 
 pub mod dto;
 
-pub async fn run<S: State, P: PeerClient>(addr: SocketAddr, node: Node<S, P>) {
-    let router = new_router(node);
-
+pub async fn run<S: State, P: PeerClient, C: Consensus>(addr: SocketAddr, node: Node<S, P, C>) {
     info!("HTTP server listening on {addr}");
+    serve(addr, new_router(node)).await
+}
 
+/// Serves the routes that manage the node itself. They are not authenticated, so
+/// `addr` should only be reachable by the operator.
+pub async fn run_admin<S: State, P: PeerClient, C: Consensus>(addr: SocketAddr, node: Node<S, P, C>) {
+    info!("Admin HTTP server listening on {addr}");
+    serve(addr, new_admin_router(node)).await
+}
+
+async fn serve(addr: SocketAddr, router: Router) {
     Server::bind(&addr)
         .serve(router.into_make_service())
         .await
         .expect("Failed to run http server");
 }
 
-pub fn new_router<S: State, P: PeerClient>(node: Node<S, P>) -> Router {
+pub fn new_router<S: State, P: PeerClient, C: Consensus>(node: Node<S, P, C>) -> Router {
+    Router::new()
+        .route("/blocks", get(get_blocks::<S, P, C>))
+        .route("/blocks/:height", get(get_block::<S, P, C>))
+        .route("/balances", get(get_balances::<S, P, C>))
+        .route("/account/version", get(get_account_version::<S, P, C>))
+        .route("/transfer", post(transfer::<S, P, C>))
+        .route("/sync", get(get_sync_status::<S, P, C>))
+        .fallback(not_found)
+        .layer(Extension(node))
+}
+
+pub fn new_admin_router<S: State, P: PeerClient, C: Consensus>(node: Node<S, P, C>) -> Router {
     Router::new()
-        .route("/blocks", get(get_blocks::<S, P>))
-        .route("/blocks/:height", get(get_block::<S, P>))
-        .route("/balances", get(get_balances::<S, P>))
-        .route("/account/version", get(get_account_version::<S, P>))
-        .route("/transfer", post(transfer::<S, P>))
+        .route("/admin/peers", get(get_peers::<S, P, C>))
+        .route("/admin/peers/:peer_id/ban", post(ban_peer::<S, P, C>))
+        .route("/admin/peers/:peer_id/unban", post(unban_peer::<S, P, C>))
         .fallback(not_found)
         .layer(Extension(node))
 }
 
-async fn get_blocks<S: State, P: PeerClient>(
-    Extension(node): Extension<Node<S, P>>,
+async fn get_blocks<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
     Query(params) : Query<GetBlocksReq>,
-) -> impl IntoResponse {
-    todo!()
+) -> Result<Json<Vec<Block>>, StatusCode> {
+    let blocks: Vec<Block> = node.state.get_blocks(params.from_height)
+        .into_iter()
+        .map(Block::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(blocks))
 }
 
-async fn get_block<S: State, P: PeerClient>(
-    Extension(_node): Extension<Node<S, P>>,
+async fn get_block<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
     Path(height): Path<u64>,
+) -> Result<Json<BlockResp>, StatusCode> {
+    let block = node.state.get_block(height).map_err(|_| StatusCode::NOT_FOUND)?;
+    let hash = block.header().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.hash();
+    let block = block.try_into().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(BlockResp { hash, block }))
+}
+
+async fn get_balances<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
 ) -> impl IntoResponse {
-    todo!()
+    Json(node.state.get_balances())
 }
 
-async fn get_balances<S: State, P: PeerClient>(
-    Extension(_node): Extension<Node<S, P>>,
+async fn get_account_version<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
+    Query(params): Query<VersionReq>
 ) -> impl IntoResponse {
-    todo!()
+    let version = node.state.version_of(&params.account);
+    Json(VersionResp { account: params.account, version })
 }
 
-async fn get_account_version<S: State, P: PeerClient>(
-    Extension(_node): Extension<Node<S, P>>,
-    Query(_params): Query<VersionReq>
+async fn transfer<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
+    Json(tx): Json<SignedTx>
+) -> Result<Json<TxResp>, (StatusCode, String)> {
+    match node.submit_tx(tx.into()) {
+        Ok(id) => Ok(Json(TxResp { id })),
+        Err(err) => Err((StatusCode::BAD_REQUEST, err.to_string())),
+    }
+}
+
+async fn get_sync_status<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
 ) -> impl IntoResponse {
-    todo!()
+    Json(node.syncer.status())
 }
 
-async fn transfer<S: State, P: PeerClient>(
-    Extension(_node): Extension<Node<S, P>>,
-    Json(_tx): Json<TxReq>
+async fn get_peers<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
 ) -> impl IntoResponse {
-    todo!()
+    Json(node.peer_client.peers())
+}
+
+async fn ban_peer<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
+    Path(peer_id): Path<String>,
+    req: Option<Json<BanReq>>,
+) -> impl IntoResponse {
+    let duration = req.and_then(|Json(req)| req.duration).map(Duration::from_secs);
+    node.peer_client.ban(&peer_id, duration);
+    StatusCode::NO_CONTENT
+}
+
+async fn unban_peer<S: State, P: PeerClient, C: Consensus>(
+    Extension(node): Extension<Node<S, P, C>>,
+    Path(peer_id): Path<String>,
+) -> StatusCode {
+    if node.peer_client.unban(&peer_id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
 }
 
 async fn not_found() -> impl IntoResponse {
@@ -0,0 +1,162 @@
+//! Addresses of the nodes this node knows of, with how reaching them went. The book
+//! is kept in the data directory so that a restarted node does not depend on its
+//! seeds again.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Name of the address book file in the data directory.
+pub const ADDRESS_BOOK_FILE: &str = "peers.json";
+
+/// Most addresses the book holds, further ones are ignored.
+pub const MAX_ADDRESSES: usize = 4096;
+
+/// Failed attempts in a row after which an address is banned.
+pub const MAX_FAILURES: u32 = 5;
+
+/// Seconds an address that keeps failing is banned for.
+pub const BAN_DURATION: u64 = 60 * 60;
+
+/// What is known about reaching one address. Times are unix timestamps, 0 for never.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AddressEntry {
+    /// Last time a handshake with the node succeeded.
+    pub last_seen: u64,
+    /// Failed attempts since then.
+    pub failures: u32,
+    /// The address is not dialed before this time.
+    pub banned_until: u64,
+}
+
+impl AddressEntry {
+    pub fn is_banned(&self, now: u64) -> bool {
+        self.banned_until > now
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    /// The book is only kept in memory when this is not set.
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, AddressEntry>,
+}
+
+impl AddressBook {
+    /// Opens the book stored at `path`, which starts out empty if there is none.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(AddressBook { path: Some(path), entries })
+    }
+
+    /// Writes the book back to where it was opened from. A crash while writing leaves
+    /// the previous book in place.
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.entries)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Adds `addr` if it is new and the book has room, returning whether it did.
+    pub fn add(&mut self, addr: &str) -> bool {
+        if self.entries.contains_key(addr) || self.entries.len() >= MAX_ADDRESSES {
+            return false;
+        }
+        self.entries.insert(addr.to_string(), AddressEntry::default());
+        true
+    }
+
+    /// Records a successful handshake with `addr`.
+    pub fn mark_seen(&mut self, addr: &str, now: u64) {
+        let entry = self.entries.entry(addr.to_string()).or_default();
+        entry.last_seen = now;
+        entry.failures = 0;
+    }
+
+    /// Records a failed attempt to reach `addr`, banning it after `MAX_FAILURES`
+    /// in a row.
+    pub fn mark_failed(&mut self, addr: &str, now: u64) {
+        let entry = self.entries.entry(addr.to_string()).or_default();
+        entry.failures += 1;
+        if entry.failures >= MAX_FAILURES {
+            entry.failures = 0;
+            entry.banned_until = now + BAN_DURATION;
+        }
+    }
+
+    /// Keeps `addr` from being dialed until `until`.
+    pub fn ban(&mut self, addr: &str, until: u64) {
+        let entry = self.entries.entry(addr.to_string()).or_default();
+        entry.banned_until = entry.banned_until.max(until);
+    }
+
+    /// Addresses worth dialing, the most promising first: fewest recent failures,
+    /// then most recently seen.
+    pub fn candidates(&self, now: u64) -> Vec<String> {
+        let mut candidates: Vec<_> = self.entries.iter()
+            .filter(|(_, entry)| !entry.is_banned(now))
+            .collect();
+        candidates.sort_by_key(|(_, entry)| (entry.failures, std::cmp::Reverse(entry.last_seen)));
+        candidates.into_iter().map(|(addr, _)| addr.clone()).collect()
+    }
+
+    /// Up to `limit` addresses to share with peers, only ones known to work and the
+    /// most recently seen first.
+    pub fn shareable(&self, now: u64, limit: usize) -> Vec<String> {
+        let mut seen: Vec<_> = self.entries.iter()
+            .filter(|(_, entry)| entry.last_seen > 0 && !entry.is_banned(now))
+            .collect();
+        seen.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.last_seen));
+        seen.into_iter().take(limit).map(|(addr, _)| addr.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    fn address_book_test() {
+        let path = std::env::temp_dir().join(format!("atman-address-book-{}.json", utils::gen_random_number::<u64>()));
+
+        let mut book = AddressBook::open(&path).unwrap();
+        assert!(book.add("10.0.0.1:7070"));
+        assert!(!book.add("10.0.0.1:7070"));
+        book.add("10.0.0.2:7070");
+        book.add("10.0.0.3:7070");
+        book.mark_seen("10.0.0.2:7070", 100);
+        book.mark_failed("10.0.0.3:7070", 100);
+        assert_eq!(book.candidates(100), ["10.0.0.2:7070", "10.0.0.1:7070", "10.0.0.3:7070"]);
+        assert_eq!(book.shareable(100, 10), ["10.0.0.2:7070"]);
+
+        for _ in 1..MAX_FAILURES {
+            book.mark_failed("10.0.0.3:7070", 100);
+        }
+        assert!(book.entries["10.0.0.3:7070"].is_banned(100));
+        assert_eq!(book.candidates(100).len(), 2);
+        assert_eq!(book.candidates(100 + BAN_DURATION).len(), 3);
+
+        book.ban("10.0.0.2:7070", 200);
+        assert!(book.shareable(100, 10).is_empty());
+
+        book.save().unwrap();
+        let reopened = AddressBook::open(&path).unwrap();
+        assert_eq!(reopened.entries, book.entries);
+        fs::remove_file(&path).unwrap();
+    }
+}
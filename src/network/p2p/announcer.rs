@@ -0,0 +1,91 @@
+//! A fixed pool of threads sending announcements, so that gossip neither holds up
+//! whoever announces nor spawns a thread per message. Announcements that find the
+//! queue full are dropped, gossip reaches the peers through other nodes anyway.
+
+use std::fmt;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Threads sending announcements.
+pub const ANNOUNCE_WORKERS: usize = 8;
+
+/// Announcements waiting for a free thread at most.
+pub const ANNOUNCE_QUEUE: usize = 1024;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Clone)]
+pub struct Announcer {
+    queue: SyncSender<Job>,
+}
+
+impl Announcer {
+    /// Starts `workers` threads, which exit once every clone of the announcer is gone.
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        let (queue, jobs) = mpsc::sync_channel::<Job>(capacity);
+        let jobs = Arc::new(Mutex::new(jobs));
+        for _ in 0..workers.max(1) {
+            let jobs = jobs.clone();
+            thread::spawn(move || loop {
+                // The lock is only held while waiting, not while sending.
+                let job = jobs.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            });
+        }
+        Announcer { queue }
+    }
+
+    /// Queues `job`, returning whether there was room for it.
+    pub fn send(&self, job: impl FnOnce() + Send + 'static) -> bool {
+        match self.queue.try_send(Box::new(job)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl Default for Announcer {
+    fn default() -> Self {
+        Announcer::new(ANNOUNCE_WORKERS, ANNOUNCE_QUEUE)
+    }
+}
+
+impl fmt::Debug for Announcer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Announcer").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn announcer_test() {
+        let announcer = Announcer::new(1, 1);
+        let (started, busy) = channel();
+        let (release, released) = channel::<()>();
+        let (done, finished) = channel();
+
+        // The only worker is busy and the queue holds one more.
+        assert!(announcer.send(move || {
+            started.send(()).unwrap();
+            released.recv().unwrap();
+        }));
+        busy.recv().unwrap();
+        let queued = done.clone();
+        assert!(announcer.send(move || queued.send(1).unwrap()));
+        assert!(!announcer.send(move || done.send(2).unwrap()));
+
+        release.send(()).unwrap();
+        assert_eq!(finished.recv_timeout(Duration::from_secs(5)).unwrap(), 1);
+        assert!(finished.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
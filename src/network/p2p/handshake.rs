@@ -0,0 +1,142 @@
+//! First exchange on every connection. It keeps apart nodes of other networks, or
+//! speaking a protocol version this node no longer understands, before they get to
+//! exchange any block.
+
+use p2p::NodeKey;
+use serde::Serialize;
+
+use crate::biz::genesis::Genesis;
+use crate::error::Error;
+use crate::schema::v1::NodeInfo;
+use crate::types::{Address, Hash};
+
+/// Version of the protocol this node speaks.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest version of the protocol this node still speaks. Version 1 synced with
+/// `Blocks` requests, which are gone.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// What the local node tells peers about itself, and the key it proves its id with.
+#[derive(Debug, Clone)]
+pub struct LocalNode {
+    pub chain_id: String,
+    pub genesis_hash: Hash,
+    /// Public part of `key`.
+    pub node_id: Address,
+    pub key: NodeKey,
+}
+
+/// What was agreed with a peer in the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PeerInfo {
+    pub node_id: Address,
+    /// Highest version both sides speak, which the connection uses.
+    pub protocol_version: u32,
+    /// Height of the peer's best block at the time of the handshake.
+    pub best_height: u64,
+}
+
+impl PeerInfo {
+    /// How the peer is referred to in `PeerClient` methods.
+    pub fn peer_id(&self) -> String {
+        self.node_id.to_string()
+    }
+}
+
+impl LocalNode {
+    pub fn new(genesis: &Genesis, key: NodeKey) -> Self {
+        LocalNode {
+            chain_id: genesis.chain_id.clone(),
+            genesis_hash: genesis.hash(),
+            node_id: key.public().into(),
+            key,
+        }
+    }
+
+    pub fn handshake(&self, best_height: u64) -> NodeInfo {
+        NodeInfo {
+            protocol_version: PROTOCOL_VERSION,
+            chain_id: self.chain_id.clone(),
+            genesis_hash: self.genesis_hash.into(),
+            best_height,
+            node_id: self.node_id.into(),
+        }
+    }
+
+    /// Checks the handshake of a peer against the local node. `remote_key` is the key
+    /// the peer authenticated the connection with, which its node id has to be.
+    pub fn accept(&self, handshake: &NodeInfo, remote_key: Address) -> Result<PeerInfo, Error> {
+        if handshake.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(Error::UnsupportedProtocolVersion { min: MIN_PROTOCOL_VERSION, actual: handshake.protocol_version });
+        }
+        if handshake.chain_id != self.chain_id {
+            return Err(Error::ChainIdMismatch { expected: self.chain_id.clone(), actual: handshake.chain_id.clone() });
+        }
+        let genesis_hash = to_bytes(&handshake.genesis_hash)?;
+        if genesis_hash != self.genesis_hash {
+            return Err(Error::PeerGenesisMismatch { expected: self.genesis_hash, actual: genesis_hash });
+        }
+        let node_id = to_bytes(&handshake.node_id)?;
+        if node_id != remote_key {
+            return Err(Error::PeerIdentityMismatch { expected: remote_key, actual: node_id });
+        }
+        if node_id == self.node_id {
+            return Err(Error::SelfConnection);
+        }
+
+        Ok(PeerInfo {
+            node_id,
+            protocol_version: handshake.protocol_version.min(PROTOCOL_VERSION),
+            best_height: handshake.best_height,
+        })
+    }
+}
+
+// `From<Vec<u8>>` panics on a length mismatch, which a peer must not be able to cause.
+fn to_bytes(bytes: &[u8]) -> Result<Hash, Error> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| Error::InvalidRequest)?;
+    Ok(bytes.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept_test() {
+        let genesis = Genesis::default();
+        let local = LocalNode::new(&genesis, NodeKey::generate());
+        let remote = LocalNode::new(&genesis, NodeKey::generate());
+
+        let info = local.accept(&remote.handshake(7), remote.node_id).unwrap();
+        assert_eq!(info, PeerInfo { node_id: remote.node_id, protocol_version: PROTOCOL_VERSION, best_height: 7 });
+        assert_eq!(info.peer_id(), remote.node_id.to_string());
+
+        let mut newer = remote.handshake(7);
+        newer.protocol_version = PROTOCOL_VERSION + 1;
+        assert_eq!(local.accept(&newer, remote.node_id).unwrap().protocol_version, PROTOCOL_VERSION);
+
+        let mut older = remote.handshake(7);
+        older.protocol_version = MIN_PROTOCOL_VERSION - 1;
+        assert!(matches!(local.accept(&older, remote.node_id), Err(Error::UnsupportedProtocolVersion { .. })));
+
+        let other = Genesis { chain_id: "other".to_string(), ..Default::default() };
+        let handshake = LocalNode::new(&other, remote.key.clone()).handshake(7);
+        assert!(matches!(local.accept(&handshake, remote.node_id), Err(Error::ChainIdMismatch { .. })));
+
+        let other = Genesis { timestamp: 1, ..Default::default() };
+        let handshake = LocalNode::new(&other, remote.key.clone()).handshake(7);
+        assert!(matches!(local.accept(&handshake, remote.node_id), Err(Error::PeerGenesisMismatch { .. })));
+
+        let mut truncated = remote.handshake(7);
+        truncated.genesis_hash.pop();
+        assert!(matches!(local.accept(&truncated, remote.node_id), Err(Error::InvalidRequest)));
+
+        // A node cannot claim the id of another.
+        let impostor: Address = NodeKey::generate().public().into();
+        assert!(matches!(local.accept(&remote.handshake(7), impostor), Err(Error::PeerIdentityMismatch { .. })));
+
+        assert!(matches!(local.accept(&local.handshake(7), local.node_id), Err(Error::SelfConnection)));
+    }
+}
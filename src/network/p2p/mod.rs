@@ -0,0 +1,774 @@
+//! Peer client and server speaking the protobuf `Request`/`Response` messages over the
+//! `p2p` TCP transport. A peer is identified by its node id, the key it authenticated
+//! the connection with, in hex.
+//!
+//! Besides the configured peers, the client dials addresses from its address book
+//! until it has enough peers, and learns more addresses from the peers it is
+//! connected to. Seeds are where the book starts from.
+//!
+//! New txs and blocks spread by gossip: each node validates what it is announced and
+//! relays it to a few random peers, skipping anything it has seen before.
+//!
+//! Peers that misbehave lose reputation and are banned once it runs out. Gossip and
+//! sync favour the peers with the best reputation.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use address_book::{AddressBook, BAN_DURATION};
+use announcer::Announcer;
+use handshake::{LocalNode, PeerInfo};
+use log::{debug, info, warn};
+use p2p::Gossip;
+use peer::PeerConnection;
+use reputation::{Reputation, ReputationConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::biz::peer_client::{Offense, PeerClient, PeerStatus};
+use crate::biz::state::State;
+use crate::error::Error;
+use crate::schema::v1::{AddressesResp, Block, BlockBodiesResp, BlockBody, BlockHeader, BlockHeightResp, HandshakeResp, HeadersResp, Request, Response, SignedTx};
+use crate::types::{Address, Hash};
+use crate::utils;
+
+pub mod address_book;
+pub mod announcer;
+pub mod handshake;
+pub mod peer;
+pub mod reputation;
+pub mod server;
+
+/// Ids of txs and blocks remembered as seen, per kind.
+pub const SEEN_CAPACITY: usize = 8192;
+
+/// Most addresses exchanged in a single message.
+pub const MAX_ADDRESSES_PER_MESSAGE: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct P2pConfig {
+    /// Address peers connect to.
+    pub listen_addr: SocketAddr,
+    /// Addresses of peers to stay connected to, on top of the discovered ones.
+    pub peers: Vec<String>,
+    /// Addresses to start discovering peers from when the address book knows no
+    /// better.
+    pub seeds: Vec<String>,
+    /// Address peers can reach this node at, which is advertised to them. Nothing is
+    /// advertised when this is not set, e.g. behind a NAT.
+    pub external_addr: Option<String>,
+    /// Peers the node dials until it is connected to as many.
+    pub max_outbound: usize,
+    /// Seconds between two rounds of peer discovery.
+    pub discovery_interval: u64,
+    /// Seconds to wait on a peer to connect or answer before giving up.
+    pub timeout: u64,
+    /// Hex X25519 private key the node authenticates connections with, its public key
+    /// being the node id. A new one is made on every start when this is not set.
+    pub node_key: Option<String>,
+    /// Peers a new tx or block is relayed to.
+    pub fanout: usize,
+    pub reputation: ReputationConfig,
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        P2pConfig {
+            listen_addr: SocketAddr::from(([0, 0, 0, 0], 7070)),
+            peers: vec![],
+            seeds: vec![],
+            external_addr: None,
+            max_outbound: 8,
+            discovery_interval: 30,
+            timeout: 10,
+            node_key: None,
+            fanout: 8,
+            reputation: ReputationConfig::default(),
+        }
+    }
+}
+
+type Sessions = Arc<RwLock<HashMap<String, Arc<Mutex<Session>>>>>;
+
+/// Peer client keeping one connection open to every peer it dialed, which is opened
+/// again when it closes while in use. Requests to the same peer share it.
+///
+/// Every connection starts with a handshake, which tells the peer the best height
+/// of `state`.
+#[derive(Debug, Clone)]
+pub struct P2pClient<S: State> {
+    config: Arc<P2pConfig>,
+    local: LocalNode,
+    state: S,
+    timeout: Duration,
+    sessions: Sessions,
+    book: Arc<Mutex<AddressBook>>,
+    txs: Arc<Gossip<Hash>>,
+    blocks: Arc<Gossip<Hash>>,
+    reputation: Arc<Reputation>,
+    announcer: Announcer,
+}
+
+/// A connection to a peer, the address it was dialed at and what was agreed on it.
+#[derive(Debug, Clone)]
+struct Session {
+    addr: String,
+    connection: Arc<PeerConnection>,
+    info: PeerInfo,
+}
+
+impl <S: State>P2pClient<S> {
+    /// Creates a client discovering peers into `book`, which the seeds are added to.
+    pub fn new(config: &P2pConfig, local: LocalNode, state: S, mut book: AddressBook) -> Self {
+        for seed in &config.seeds {
+            book.add(seed);
+        }
+        P2pClient {
+            config: Arc::new(config.clone()),
+            local,
+            state,
+            timeout: Duration::from_secs(config.timeout),
+            sessions: Default::default(),
+            book: Arc::new(Mutex::new(book)),
+            txs: Arc::new(Gossip::new(config.fanout, SEEN_CAPACITY)),
+            blocks: Arc::new(Gossip::new(config.fanout, SEEN_CAPACITY)),
+            reputation: Arc::new(Reputation::new(config.reputation)),
+            announcer: Announcer::default(),
+        }
+    }
+
+    /// What was agreed with `peer_id` on the current connection to it, if any.
+    pub fn peer_info(&self, peer_id: &str) -> Option<PeerInfo> {
+        let session = self.session(peer_id)?;
+        let session = session.lock().unwrap();
+        (!session.connection.is_closed()).then_some(session.info)
+    }
+
+    pub fn is_banned(&self, peer_id: &str) -> bool {
+        self.reputation.is_banned(peer_id, utils::unix_timestamp())
+    }
+
+    /// Records the tx with `id` as seen, returning whether it is new.
+    pub fn mark_tx_seen(&self, id: Hash) -> bool {
+        self.txs.mark_seen(id)
+    }
+
+    /// Records the block with `hash` as seen, returning whether it is new.
+    pub fn mark_block_seen(&self, hash: Hash) -> bool {
+        self.blocks.mark_seen(hash)
+    }
+
+    pub fn is_tx_seen(&self, id: &Hash) -> bool {
+        self.txs.is_seen(id)
+    }
+
+    pub fn is_block_seen(&self, hash: &Hash) -> bool {
+        self.blocks.is_seen(hash)
+    }
+
+    /// Relays a tx announced by the node `origin` once it has been validated.
+    pub fn relay_tx(&self, tx: SignedTx, origin: Address) {
+        self.gossip(Request::new_tx_request(tx), &self.txs, Some(origin));
+    }
+
+    /// Relays a block announced by the node `origin` once it has been validated.
+    pub fn relay_block(&self, block: Block, origin: Address) {
+        self.gossip(Request::new_block_request(block), &self.blocks, Some(origin));
+    }
+
+    /// Peers with an open connection.
+    pub fn connected_peers(&self) -> Vec<String> {
+        self.known_peers().into_iter().filter(|peer_id| self.peer_info(peer_id).is_some()).collect()
+    }
+
+    /// Addresses to tell peers about.
+    pub fn shareable_addresses(&self) -> Vec<String> {
+        self.book.lock().unwrap().shareable(utils::unix_timestamp(), MAX_ADDRESSES_PER_MESSAGE)
+    }
+
+    /// Adds the well-formed ones among `addresses` to the address book, returning
+    /// how many were new.
+    pub fn add_addresses(&self, addresses: Vec<String>) -> usize {
+        let mut book = self.book.lock().unwrap();
+        addresses.into_iter()
+            .take(MAX_ADDRESSES_PER_MESSAGE)
+            .filter(|addr| addr.parse::<SocketAddr>().is_ok() && Some(addr) != self.config.external_addr.as_ref())
+            .filter(|addr| book.add(addr))
+            .count()
+    }
+
+    /// Asks `peer_id` for the addresses it knows.
+    pub fn get_addresses(&self, peer_id: &str) -> Result<Vec<String>, Error> {
+        let response: AddressesResp = self.typed_request(peer_id, Request::new_get_addresses_request())?;
+        Ok(response.addresses)
+    }
+
+    /// Connects to the node at `addr` unless already connected to it, returning its
+    /// peer id.
+    pub fn connect(&self, addr: &str) -> Result<String, Error> {
+        if let Some(peer_id) = self.peer_at(addr) {
+            return Ok(peer_id);
+        }
+        let session = self.open(addr)?;
+        let peer_id = session.info.peer_id();
+        let mut sessions = self.sessions.write().unwrap();
+        // The node may be reachable at several addresses, one connection is enough.
+        let connected = sessions.get(&peer_id).is_some_and(|session| {
+            // A session that is busy is being reconnected.
+            session.try_lock().map_or(true, |session| !session.connection.is_closed())
+        });
+        if !connected {
+            sessions.insert(peer_id.clone(), Arc::new(Mutex::new(session)));
+        }
+        Ok(peer_id)
+    }
+
+    /// One round of discovery: drops closed connections, connects to the configured
+    /// peers, then dials addresses from the book while fewer than
+    /// `P2pConfig::max_outbound` peers are connected. Connected peers are asked for
+    /// more addresses, and the new ones dialed. The book is saved after.
+    pub fn maintain(&self) {
+        self.sessions.write().unwrap().retain(|_, session| {
+            // A session that is busy is being reconnected.
+            session.try_lock().map_or(true, |session| !session.connection.is_closed())
+        });
+        for addr in &self.config.peers {
+            if let Err(err) = self.connect(addr) {
+                debug!("Failed to connect to {addr}: {err}");
+            }
+        }
+        self.dial();
+        let mut learned = 0;
+        for peer_id in self.connected_peers() {
+            match self.get_addresses(&peer_id) {
+                Ok(addresses) => {
+                    let added = self.add_addresses(addresses);
+                    if added > 0 {
+                        debug!("Learned {added} addresses from {peer_id}");
+                    }
+                    learned += added;
+                }
+                Err(err) => debug!("Failed to get addresses from {peer_id}: {err}"),
+            }
+        }
+        if learned > 0 {
+            self.dial();
+        }
+
+        if let Err(err) = self.book.lock().unwrap().save() {
+            warn!("Failed to save address book: {err}");
+        }
+    }
+
+    /// Keeps discovering peers every `P2pConfig::discovery_interval` until `stop`
+    /// is set.
+    pub fn run_discovery(&self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            self.maintain();
+            thread::sleep(Duration::from_secs(self.config.discovery_interval));
+        }
+    }
+
+    /// Connects to addresses from the book until enough peers are connected.
+    fn dial(&self) {
+        let mut missing = self.config.max_outbound.saturating_sub(self.connected_peers().len());
+        let candidates = self.book.lock().unwrap().candidates(utils::unix_timestamp());
+        for addr in candidates {
+            if missing == 0 {
+                break;
+            }
+            if self.peer_at(&addr).is_some() || Some(&addr) == self.config.external_addr.as_ref() {
+                continue;
+            }
+            if self.connect(&addr).is_ok() {
+                missing -= 1;
+            }
+        }
+    }
+
+    fn session(&self, peer_id: &str) -> Option<Arc<Mutex<Session>>> {
+        self.sessions.read().unwrap().get(peer_id).cloned()
+    }
+
+    /// Peer id of the open connection to `addr`, if any.
+    fn peer_at(&self, addr: &str) -> Option<String> {
+        self.sessions.read().unwrap().iter()
+            .find(|(_, session)| session.try_lock().is_ok_and(|session| session.addr == addr && !session.connection.is_closed()))
+            .map(|(peer_id, _)| peer_id.clone())
+    }
+
+    /// Sends `request` to `peer_id`, reporting the peer if it answers with something
+    /// that does not decode or with the response to another method.
+    fn request(&self, peer_id: &str, request: Request) -> Result<Response, Error> {
+        let method = request.method;
+        let connection = self.connection(peer_id)?;
+        let result = connection.request(request, self.timeout);
+        if connection.take_invalid_message() {
+            self.report(peer_id, Offense::MalformedMessage);
+        }
+        let response = result?;
+        if response.method != method {
+            self.report(peer_id, Offense::UnexpectedResponse);
+            return Err(Error::InvalidResponse);
+        }
+        Ok(response)
+    }
+
+    /// Like `request`, also reporting the peer if the response has the wrong body.
+    fn typed_request<T: TryFrom<Response, Error = Error>>(&self, peer_id: &str, request: Request) -> Result<T, Error> {
+        let response = self.request(peer_id, request)?;
+        T::try_from(response).inspect_err(|_| self.report(peer_id, Offense::UnexpectedResponse))
+    }
+
+    /// Drops and closes the connection to `peer_id`, if any.
+    fn disconnect(&self, peer_id: &str) {
+        if let Some(session) = self.sessions.write().unwrap().remove(peer_id) {
+            // A session that is busy is being reconnected, and closed once it fails
+            // the ban check.
+            if let Ok(session) = session.try_lock() {
+                session.connection.close();
+            }
+        }
+    }
+
+    /// The connection to `peer_id`, reopened if it closed. The node found at its
+    /// address then has to be the same.
+    fn connection(&self, peer_id: &str) -> Result<Arc<PeerConnection>, Error> {
+        let session = self.session(peer_id).ok_or_else(|| Error::UnknownPeer(peer_id.to_string()))?;
+        let mut session = session.lock().unwrap();
+        if session.connection.is_closed() {
+            let reopened = self.open(&session.addr)?;
+            if reopened.info.node_id != session.info.node_id {
+                reopened.connection.close();
+                return Err(Error::PeerIdentityMismatch { expected: session.info.node_id, actual: reopened.info.node_id });
+            }
+            *session = reopened;
+        }
+        Ok(session.connection.clone())
+    }
+
+    /// Opens a connection to `addr` and shakes hands on it, closing it again if the
+    /// peer turns out not to match or is banned. The outcome goes into the address
+    /// book, and the peer is told the external address of this node.
+    fn open(&self, addr: &str) -> Result<Session, Error> {
+        let result = self.handshake(addr).and_then(|session| {
+            let peer_id = session.info.peer_id();
+            if self.is_banned(&peer_id) {
+                session.connection.close();
+                return Err(Error::PeerBanned(peer_id));
+            }
+            Ok(session)
+        });
+        let now = utils::unix_timestamp();
+        let mut book = self.book.lock().unwrap();
+        match &result {
+            Ok(_) => book.mark_seen(addr, now),
+            // The ban is on the node rather than the address, which is tried again
+            // once the ban is over.
+            Err(Error::PeerBanned(_)) => {}
+            // Nodes of other networks, or this very node, will not get any better.
+            Err(Error::ChainIdMismatch { .. } | Error::PeerGenesisMismatch { .. }
+                | Error::UnsupportedProtocolVersion { .. } | Error::SelfConnection) => book.ban(addr, now + BAN_DURATION),
+            Err(_) => book.mark_failed(addr, now),
+        }
+        drop(book);
+
+        let session = result?;
+        if let Some(external_addr) = &self.config.external_addr {
+            let request = Request::new_addresses_request(vec![external_addr.clone()]);
+            if let Err(err) = session.connection.request(request, self.timeout) {
+                debug!("Failed to advertise {external_addr} to {addr}: {err}");
+            }
+        }
+        Ok(session)
+    }
+
+    fn handshake(&self, addr: &str) -> Result<Session, Error> {
+        let connection = Arc::new(PeerConnection::connect(addr, self.timeout, &self.local.key)?);
+        let request = Request::new_handshake_request(self.local.handshake(self.state.block_height()));
+        let info = connection.request(request, self.timeout)
+            .and_then(HandshakeResp::try_from)
+            .and_then(|resp| resp.node.ok_or(Error::InvalidResponse))
+            .and_then(|handshake| self.local.accept(&handshake, connection.remote_key().into()));
+        match info {
+            Ok(info) => {
+                info!("Connected to {addr}, node {} at height {} speaking protocol version {}",
+                    info.node_id, info.best_height, info.protocol_version);
+                Ok(Session { addr: addr.to_string(), connection, info })
+            }
+            Err(err) => {
+                warn!("Disconnected from {addr}: {err}");
+                connection.close();
+                Err(err)
+            }
+        }
+    }
+
+    /// Queues `request` for the peers `gossip` picks, the reputable ones more likely,
+    /// so that announcing never holds up the caller. The node it came from is left
+    /// out, it has the message already.
+    fn gossip(&self, request: Request, gossip: &Gossip<Hash>, origin: Option<Address>) {
+        let origin = origin.map(|origin| origin.to_string());
+        let now = utils::unix_timestamp();
+        let peers = self.known_peers().into_iter()
+            .filter(|peer_id| Some(peer_id) != origin.as_ref())
+            .map(|peer_id| {
+                let weight = self.reputation.weight(&peer_id, now);
+                (peer_id, weight)
+            })
+            .collect();
+        for peer_id in gossip.pick(peers) {
+            let client = self.clone();
+            let request = request.clone();
+            let queued = self.announcer.send(move || {
+                if let Err(err) = client.request(&peer_id, request) {
+                    debug!("Failed to announce to {peer_id}: {err}");
+                }
+            });
+            if !queued {
+                debug!("Dropped an announcement, the queue is full");
+            }
+        }
+    }
+}
+
+impl <S: State>PeerClient for P2pClient<S> {
+    fn known_peers(&self) -> Vec<String> {
+        let now = utils::unix_timestamp();
+        let mut peers: Vec<_> = self.sessions.read().unwrap().keys()
+            .filter(|peer_id| !self.reputation.is_banned(peer_id, now))
+            .map(|peer_id| (self.reputation.score(peer_id, now), peer_id.clone()))
+            .collect();
+        peers.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        peers.into_iter().map(|(_, peer_id)| peer_id).collect()
+    }
+
+    fn get_block_height(&self, peer_id: &str) -> Result<u64, Error> {
+        let response: BlockHeightResp = self.typed_request(peer_id, Request::new_block_height_request())?;
+        Ok(response.height)
+    }
+
+    fn get_headers(&self, peer_id: &str, from_height: u64, limit: u32) -> Result<Vec<BlockHeader>, Error> {
+        let response: HeadersResp = self.typed_request(peer_id, Request::new_get_headers_request(from_height, limit))?;
+        if response.headers.len() > limit as usize {
+            self.report(peer_id, Offense::UnexpectedResponse);
+            return Err(Error::InvalidResponse);
+        }
+        Ok(response.headers)
+    }
+
+    fn get_block_bodies(&self, peer_id: &str, hashes: &[Hash]) -> Result<Vec<BlockBody>, Error> {
+        let request = Request::new_get_block_bodies_request(hashes.iter().map(|hash| hash.to_vec()).collect());
+        let response: BlockBodiesResp = self.typed_request(peer_id, request)?;
+        if response.bodies.len() > hashes.len() {
+            self.report(peer_id, Offense::UnexpectedResponse);
+            return Err(Error::InvalidResponse);
+        }
+        Ok(response.bodies)
+    }
+
+    fn broadcast_tx(&self, tx: SignedTx) {
+        match tx.raw_tx() {
+            Ok(raw_tx) if self.mark_tx_seen(raw_tx.id()) => self.gossip(Request::new_tx_request(tx), &self.txs, None),
+            _ => {}
+        }
+    }
+
+    fn broadcast_block(&self, block: Block) {
+        match block.header() {
+            Ok(header) if self.mark_block_seen(header.hash()) => self.gossip(Request::new_block_request(block), &self.blocks, None),
+            _ => {}
+        }
+    }
+
+    fn report(&self, peer_id: &str, offense: Offense) {
+        let now = utils::unix_timestamp();
+        if self.reputation.penalize(peer_id, offense, now) {
+            warn!("Banned {peer_id} for {}s after {offense:?}", self.reputation.ban_duration());
+            self.disconnect(peer_id);
+        } else {
+            debug!("Reported {peer_id} for {offense:?}, score {:.1}", self.reputation.score(peer_id, now));
+        }
+    }
+
+    fn peers(&self) -> Vec<PeerStatus> {
+        let now = utils::unix_timestamp();
+        let mut peers: Vec<_> = self.connected_peers().into_iter()
+            .map(|peer_id| PeerStatus {
+                score: self.reputation.score(&peer_id, now),
+                peer_id,
+                connected: true,
+                banned_until: None,
+            })
+            .collect();
+        for status in self.reputation.penalized(now) {
+            if !peers.iter().any(|peer| peer.peer_id == status.peer_id) {
+                peers.push(status);
+            }
+        }
+        peers
+    }
+
+    fn ban(&self, peer_id: &str, duration: Option<Duration>) {
+        let duration = duration.map_or(self.reputation.ban_duration(), |duration| duration.as_secs());
+        self.reputation.ban(peer_id, utils::unix_timestamp() + duration);
+        info!("Banned {peer_id} for {duration}s");
+        self.disconnect(peer_id);
+    }
+
+    fn unban(&self, peer_id: &str) -> bool {
+        self.reputation.unban(peer_id, utils::unix_timestamp())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use p2p::NodeKey;
+    use wallet::Wallet;
+
+    use super::*;
+    use crate::biz::genesis::Genesis;
+    use crate::biz::mempool::{Mempool, MempoolConfig};
+    use crate::biz::Node;
+    use crate::consensus::pow::ProofOfWork;
+    use crate::data::memory_state::MemoryState;
+    use crate::schema::v1::Tx;
+    use crate::types::{Address, Bytes};
+
+    type TestNode = Node<MemoryState<ProofOfWork>, P2pClient<MemoryState<ProofOfWork>>, ProofOfWork>;
+
+    /// Starts a node serving peers on a free port.
+    fn start_node(genesis: &Genesis) -> (TestNode, String) {
+        start_node_with(genesis, P2pConfig::default())
+    }
+
+    fn start_node_with(genesis: &Genesis, config: P2pConfig) -> (TestNode, String) {
+        let config = P2pConfig { timeout: 1, ..config };
+        let local = LocalNode::new(genesis, NodeKey::generate());
+        let state = MemoryState::new(genesis, ProofOfWork::default());
+        let mempool = Mempool::new(MempoolConfig::default(), genesis.consensus);
+        let node = Node::new(state.clone(), P2pClient::new(&config, local.clone(), state, AddressBook::default()), ProofOfWork::default(), mempool);
+        let addr = server::start(SocketAddr::from(([127, 0, 0, 1], 0)), local, node.clone()).unwrap();
+        (node, addr.to_string())
+    }
+
+    fn peer_id(node: &TestNode) -> String {
+        node.peer_client.local.node_id.to_string()
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn sync_and_broadcast_test() {
+        let alice = Wallet::new();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        let (remote, addr) = start_node(&genesis);
+        let author: Address = Bytes::<32>::new_for_test();
+        for _ in 0..3 {
+            let parent = remote.state.get_blocks(0).pop().unwrap();
+            remote.add_block(Block::new_child_for_test(Some(parent.header().unwrap()), author, vec![])).unwrap();
+        }
+
+        let (local, _) = start_node(&genesis);
+        // Peers go by the node id they authenticated with, whatever the address.
+        let peer = local.peer_client.connect(&addr).unwrap();
+        assert_eq!(peer, peer_id(&remote));
+        assert_eq!(local.peer_client.connect(&addr).unwrap(), peer);
+        assert_eq!(local.peer_client.known_peers(), [peer.as_str()]);
+        assert_eq!(local.peer_client.get_block_height(&peer).unwrap(), 3);
+        assert_eq!(local.peer_client.peer_info(&peer).unwrap().best_height, 3);
+        assert!(local.peer_client.connect("127.0.0.1:1").is_err());
+        assert!(matches!(local.peer_client.get_block_height(&addr), Err(Error::UnknownPeer(_))));
+        let mut request = Request::new_get_headers_request(0, 1);
+        request.body = None;
+        assert!(matches!(local.peer_client.request(&peer, request), Err(Error::PeerFailed { .. })));
+
+        local.sync(&AtomicBool::new(false));
+        assert_eq!(local.state.last_block_hash(), remote.state.last_block_hash());
+
+        let tx = Tx::new(alice.address().into(), Bytes::<32>::new_for_test(), 10, 1).sign(&alice);
+        let id = local.submit_tx(tx).unwrap();
+        assert!(wait_until(|| remote.mempool.contains(&id)));
+
+        let parent = local.state.get_blocks(0).pop().unwrap();
+        let block = Block::new_child_for_test(Some(parent.header().unwrap()), author, vec![]);
+        local.peer_client.broadcast_block(block.clone());
+        assert!(wait_until(|| remote.state.has_block(&block.header().unwrap().hash())));
+
+        // A closed connection is opened again on the next request.
+        local.peer_client.session(&peer).unwrap().lock().unwrap().connection.close();
+        assert_eq!(local.peer_client.get_block_height(&peer).unwrap(), 4);
+    }
+
+    #[test]
+    fn gossip_test() {
+        let alice = Wallet::new();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        // Carol only hears from Alice through Bob.
+        let (carol, carol_addr) = start_node(&genesis);
+        let (bob, bob_addr) = start_node(&genesis);
+        let (alice_node, _) = start_node(&genesis);
+        bob.peer_client.connect(&carol_addr).unwrap();
+        alice_node.peer_client.connect(&bob_addr).unwrap();
+
+        // Bob refuses a tx from a sender without funds and does not relay it.
+        let broke = Wallet::new();
+        let invalid = Tx::new(broke.address().into(), Bytes::<32>::new_for_test(), 10, 1).sign(&broke);
+        let invalid_id = invalid.raw_tx().unwrap().id();
+        alice_node.peer_client.broadcast_tx(invalid);
+
+        let tx = Tx::new(alice.address().into(), Bytes::<32>::new_for_test(), 10, 1).sign(&alice);
+        let id = alice_node.submit_tx(tx).unwrap();
+        assert!(wait_until(|| carol.mempool.contains(&id)));
+        assert!(!bob.peer_client.txs.is_seen(&invalid_id));
+        assert!(!carol.peer_client.txs.is_seen(&invalid_id));
+
+        let parent = alice_node.state.get_blocks(0).pop().unwrap();
+        let block = Block::new_child_for_test(Some(parent.header().unwrap()), Bytes::<32>::new_for_test(), vec![]);
+        let hash = block.header().unwrap().hash();
+        alice_node.peer_client.broadcast_block(block.clone());
+        assert!(wait_until(|| carol.state.has_block(&hash)));
+        // Announcing it again is a no-op.
+        assert!(!alice_node.peer_client.mark_block_seen(hash));
+    }
+
+    #[test]
+    fn forged_copy_test() {
+        let alice = Wallet::new();
+        let genesis = Genesis { alloc: [(alice.address().into(), 1000)].into(), ..Default::default() };
+        let (carol, carol_addr) = start_node(&genesis);
+        let (bob, bob_addr) = start_node(&genesis);
+        let (mallory, _) = start_node(&genesis);
+        let (alice_node, _) = start_node(&genesis);
+        bob.peer_client.connect(&carol_addr).unwrap();
+        let bob_id = mallory.peer_client.connect(&bob_addr).unwrap();
+        alice_node.peer_client.connect(&bob_addr).unwrap();
+
+        // Ids and hashes leave the signatures out, so a copy with a broken one arrives
+        // under the same key as the real thing. It must not keep the real one out.
+        let tx = Tx::new(alice.address().into(), Bytes::<32>::new_for_test(), 10, 1).sign(&alice);
+        let mut forged = tx.clone();
+        forged.signature[1] ^= 1;
+        mallory.peer_client.request(&bob_id, Request::new_tx_request(forged)).unwrap();
+        let id = alice_node.submit_tx(tx.clone()).unwrap();
+        assert!(wait_until(|| carol.mempool.contains(&id)));
+
+        let parent = alice_node.state.get_blocks(0).pop().unwrap();
+        let block = Block::new_child_for_test(Some(parent.header().unwrap()), Bytes::<32>::new_for_test(), vec![tx]);
+        let hash = block.header().unwrap().hash();
+        let mut forged = block.clone();
+        forged.txs[0].signature[1] ^= 1;
+        mallory.peer_client.request(&bob_id, Request::new_block_request(forged)).unwrap();
+        assert!(!bob.state.has_block(&hash));
+        alice_node.peer_client.broadcast_block(block);
+        assert!(wait_until(|| carol.state.has_block(&hash)));
+    }
+
+    #[test]
+    fn discovery_test() {
+        let genesis = Genesis::default();
+        let (carol, carol_addr) = start_node(&genesis);
+        let (bob, bob_addr) = start_node_with(&genesis, P2pConfig { peers: vec![carol_addr], ..Default::default() });
+        bob.peer_client.maintain();
+        assert_eq!(bob.peer_client.connected_peers(), [peer_id(&carol)]);
+
+        // Alice only knows Bob as a seed, and finds Carol through him.
+        let config = P2pConfig { seeds: vec![bob_addr], external_addr: Some("127.0.0.1:9".to_string()), ..Default::default() };
+        let (alice, _) = start_node_with(&genesis, config);
+        assert!(alice.peer_client.known_peers().is_empty());
+        alice.peer_client.maintain();
+        let mut peers = alice.peer_client.connected_peers();
+        peers.sort();
+        let mut expected = vec![peer_id(&bob), peer_id(&carol)];
+        expected.sort();
+        assert_eq!(peers, expected);
+        // Alice told Bob where to reach her.
+        assert!(bob.peer_client.book.lock().unwrap().candidates(utils::unix_timestamp()).contains(&"127.0.0.1:9".to_string()));
+
+        // Peers that cannot be reached are not kept.
+        let config = P2pConfig { seeds: vec!["127.0.0.1:1".to_string()], ..Default::default() };
+        let (dave, _) = start_node_with(&genesis, config);
+        dave.peer_client.maintain();
+        assert!(dave.peer_client.known_peers().is_empty());
+    }
+
+    #[test]
+    fn handshake_test() {
+        let genesis = Genesis::default();
+        let (_, addr) = start_node(&genesis);
+
+        let other = Genesis { chain_id: "other".to_string(), ..Default::default() };
+        let (stranger, _) = start_node(&other);
+        // The server turns the stranger away with the reason.
+        let result = stranger.peer_client.connect(&addr);
+        assert!(matches!(result, Err(Error::PeerFailed { message, .. }) if message.contains("other")));
+        assert!(stranger.peer_client.known_peers().is_empty());
+
+        // The server does not serve connections that skipped the handshake.
+        let connection = PeerConnection::connect(&addr, Duration::from_secs(1), &NodeKey::generate()).unwrap();
+        let result = connection.request(Request::new_block_height_request(), Duration::from_secs(1));
+        assert!(matches!(result, Err(Error::PeerFailed { .. })));
+        assert!(wait_until(|| connection.is_closed()));
+    }
+
+    #[test]
+    fn malformed_block_test() {
+        let genesis = Genesis::default();
+        let (remote, addr) = start_node(&genesis);
+        let (local, _) = start_node(&genesis);
+        let peer = local.peer_client.connect(&addr).unwrap();
+
+        let parent = remote.state.get_blocks(0).pop().unwrap();
+        let mut block = Block::new_child_for_test(Some(parent.header().unwrap()), Bytes::<32>::new_for_test(), vec![]);
+        block.header_mut().unwrap().parent_hash.truncate(31);
+        local.peer_client.request(&peer, Request::new_block_request(block)).unwrap();
+
+        // The block is refused and the node keeps serving.
+        assert_eq!(local.peer_client.get_block_height(&peer).unwrap(), 0);
+        assert_eq!(remote.state.block_height(), 0);
+    }
+
+    #[test]
+    fn reputation_test() {
+        let genesis = Genesis::default();
+        let (remote, addr) = start_node(&genesis);
+        let (local, _) = start_node(&genesis);
+        let peer = local.peer_client.connect(&addr).unwrap();
+
+        local.peer_client.report(&peer, Offense::InvalidBlock);
+        assert_eq!(local.peer_client.known_peers(), [peer.as_str()]);
+        assert!(local.peer_client.peers()[0].score < 0.0);
+
+        local.peer_client.report(&peer, Offense::InvalidBlock);
+        assert!(local.peer_client.known_peers().is_empty());
+        assert!(matches!(local.peer_client.connect(&addr), Err(Error::PeerBanned(_))));
+        let status = &local.peer_client.peers()[0];
+        assert!(!status.connected && status.banned_until.is_some());
+        // The address stays in the book for when the ban is over.
+        assert_eq!(local.peer_client.book.lock().unwrap().candidates(utils::unix_timestamp()), [addr.as_str()]);
+
+        assert!(local.peer_client.unban(&peer));
+        assert_eq!(local.peer_client.connect(&addr).unwrap(), peer);
+
+        // A banned node is refused by the server too.
+        remote.peer_client.ban(&peer_id(&local), None);
+        assert!(local.peer_client.get_block_height(&peer).is_err());
+        assert!(wait_until(|| local.peer_client.peer_info(&peer).is_none()));
+        assert!(matches!(local.peer_client.connect(&addr), Err(Error::PeerFailed { .. })));
+    }
+}
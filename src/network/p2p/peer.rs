@@ -0,0 +1,205 @@
+//! Multiplexed connection to a peer.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+use p2p::{Connection, NodeKey, PublicKey};
+
+use crate::error::Error;
+use crate::schema::v1::{Request, Response};
+use crate::types::Address;
+
+type Pending = Arc<Mutex<HashMap<u64, SyncSender<Response>>>>;
+
+/// Connection to a peer carrying any number of requests at once. A reader thread
+/// hands every response to the request with the same id.
+#[derive(Debug)]
+pub struct PeerConnection {
+    peer_id: String,
+    remote_key: PublicKey,
+    writer: Mutex<Connection>,
+    pending: Pending,
+    next_id: AtomicU64,
+    closed: Arc<AtomicBool>,
+    /// Set when the connection closed on a message that did not decode.
+    invalid_message: Arc<AtomicBool>,
+}
+
+impl PeerConnection {
+    /// Connects to `addr`, securing the connection with `key`. The peer is referred
+    /// to by the key it authenticated with.
+    pub fn connect(addr: &str, timeout: Duration, key: &NodeKey) -> Result<Self, Error> {
+        let writer = Connection::connect(addr, timeout, key)?;
+        let peer_id = Address::from(writer.remote_key()).to_string();
+        writer.set_write_timeout(Some(timeout))?;
+        let reader = writer.try_clone()?;
+
+        let pending = Pending::default();
+        let closed = Arc::new(AtomicBool::new(false));
+        let invalid_message = Arc::new(AtomicBool::new(false));
+        {
+            let peer_id = peer_id.clone();
+            let pending = pending.clone();
+            let closed = closed.clone();
+            let invalid_message = invalid_message.clone();
+            thread::spawn(move || read_responses(&peer_id, reader, &pending, &closed, &invalid_message));
+        }
+
+        Ok(PeerConnection {
+            peer_id,
+            remote_key: writer.remote_key(),
+            writer: Mutex::new(writer),
+            pending,
+            // Id 0 is left to responses that answer no request in particular.
+            next_id: AtomicU64::new(1),
+            closed,
+            invalid_message,
+        })
+    }
+
+    /// Key the peer authenticated the connection with.
+    pub fn remote_key(&self) -> PublicKey {
+        self.remote_key
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Sends `request` and waits up to `timeout` for the response to it, which is
+    /// turned into an error if the peer reports one.
+    pub fn request(&self, mut request: Request, timeout: Duration) -> Result<Response, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        request.id = id;
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.pending.lock().unwrap().insert(id, sender);
+        // The reader sets `closed` before it drops the pending senders, so either it
+        // dropped ours as well or we see the flag here.
+        if self.is_closed() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(Error::ConnectionClosed(self.peer_id.clone()));
+        }
+
+        let sent = self.writer.lock().unwrap().send(&Vec::from(request));
+        if let Err(err) = sent {
+            self.close();
+            return Err(err.into());
+        }
+
+        let received = receiver.recv_timeout(timeout);
+        self.pending.lock().unwrap().remove(&id);
+        match received {
+            Ok(response) => response.into_result(&self.peer_id),
+            Err(RecvTimeoutError::Timeout) => Err(Error::RequestTimeout { peer_id: self.peer_id.clone(), id }),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::ConnectionClosed(self.peer_id.clone())),
+        }
+    }
+
+    /// Whether the peer sent a message that did not decode, which is only reported
+    /// once.
+    pub fn take_invalid_message(&self) -> bool {
+        self.invalid_message.swap(false, Ordering::SeqCst)
+    }
+
+    /// Fails every request in flight and ends the reader thread.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.writer.lock().unwrap().shutdown();
+    }
+}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn read_responses(peer_id: &str, mut reader: Connection, pending: &Pending, closed: &AtomicBool, invalid_message: &AtomicBool) {
+    loop {
+        let response = match reader.recv().map_err(Error::from).and_then(Response::try_from) {
+            Ok(response) => response,
+            Err(err) => {
+                if matches!(err, Error::InvalidP2pMessage(_)) {
+                    invalid_message.store(true, Ordering::SeqCst);
+                }
+                debug!("Closed connection to {peer_id}: {err}");
+                break;
+            }
+        };
+        match pending.lock().unwrap().remove(&response.id) {
+            Some(sender) => {
+                let _ = sender.send(response);
+            }
+            // The request timed out in the meantime.
+            None => debug!("Dropped response {} from {peer_id}", response.id),
+        }
+    }
+    closed.store(true, Ordering::SeqCst);
+    pending.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use p2p::{Reply, Server};
+
+    use super::*;
+    use crate::schema::v1::{BlockHeightResp, Method};
+
+    /// Answers height requests with their id, after waiting as many milliseconds.
+    fn start_server() -> String {
+        let server = Server::bind("127.0.0.1:0", NodeKey::generate()).unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        thread::spawn(move || server.serve(|_, _| |request: Vec<u8>| {
+            let request = Request::try_from(request).unwrap();
+            let mut response = if request.method == Method::Height as i32 {
+                thread::sleep(Duration::from_millis(request.id * 10));
+                Response::new_block_height_response(request.id)
+            } else {
+                Response::new_error_response(request.method, "unsupported".to_string())
+            };
+            response.id = request.id;
+            Reply::Message(response.into())
+        }));
+        addr
+    }
+
+    #[test]
+    fn multiplex_test() {
+        let connection = Arc::new(PeerConnection::connect(&start_server(), Duration::from_secs(1), &NodeKey::generate()).unwrap());
+
+        let start = Instant::now();
+        let handles: Vec<_> = (0..10).map(|_| {
+            let connection = connection.clone();
+            thread::spawn(move || {
+                let response = connection.request(Request::new_block_height_request(), Duration::from_secs(1)).unwrap();
+                (response.id, BlockHeightResp::try_from(response).unwrap().height)
+            })
+        }).collect();
+        for handle in handles {
+            let (id, height) = handle.join().unwrap();
+            assert_eq!(id, height);
+        }
+        // Sequential requests would take 10 + 20 + ... + 100 ms.
+        assert!(start.elapsed() < Duration::from_millis(550));
+
+        let result = connection.request(Request::new_get_headers_request(0, 1), Duration::from_secs(1));
+        assert!(matches!(result, Err(Error::PeerFailed { message, .. }) if message == "unsupported"));
+
+        // Request 12 takes 120 ms to answer.
+        let result = connection.request(Request::new_block_height_request(), Duration::from_millis(50));
+        assert!(matches!(result, Err(Error::RequestTimeout { id: 12, .. })));
+        assert!(!connection.is_closed());
+
+        connection.close();
+        let result = connection.request(Request::new_block_height_request(), Duration::from_secs(1));
+        assert!(matches!(result, Err(Error::ConnectionClosed(_)) | Err(Error::P2p(_))));
+        assert!(connection.is_closed());
+    }
+}
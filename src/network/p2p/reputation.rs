@@ -0,0 +1,153 @@
+//! Scores of peers, which misbehaviour lowers and time restores. A peer whose score
+//! falls to the threshold is banned for a while.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::biz::peer_client::{Offense, PeerStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReputationConfig {
+    /// Score at or below which a peer is banned.
+    pub ban_threshold: i64,
+    /// Seconds it takes the penalties of a peer to halve.
+    pub half_life: u64,
+    /// Seconds a peer is banned for.
+    pub ban_duration: u64,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        ReputationConfig {
+            ban_threshold: -100,
+            half_life: 10 * 60,
+            ban_duration: 60 * 60,
+        }
+    }
+}
+
+/// Standing of one peer. Times are unix timestamps.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Standing {
+    /// Score as of `updated`.
+    score: f64,
+    updated: u64,
+    banned_until: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Reputation {
+    config: ReputationConfig,
+    peers: Mutex<HashMap<String, Standing>>,
+}
+
+impl Reputation {
+    pub fn new(config: ReputationConfig) -> Self {
+        Reputation { config, peers: Default::default() }
+    }
+
+    pub fn ban_duration(&self) -> u64 {
+        self.config.ban_duration
+    }
+
+    /// Current score of `peer_id`, 0 for peers that never misbehaved.
+    pub fn score(&self, peer_id: &str, now: u64) -> f64 {
+        self.peers.lock().unwrap().get(peer_id).map_or(0.0, |standing| self.decayed(standing, now))
+    }
+
+    /// Lowers the score of `peer_id` for `offense`, returning whether that got it
+    /// banned.
+    pub fn penalize(&self, peer_id: &str, offense: Offense, now: u64) -> bool {
+        let mut peers = self.peers.lock().unwrap();
+        let standing = peers.entry(peer_id.to_string()).or_default();
+        standing.score = self.decayed(standing, now) - offense.penalty();
+        standing.updated = now;
+        if standing.score > self.config.ban_threshold as f64 || is_banned(standing, now) {
+            return false;
+        }
+        standing.banned_until = Some(now + self.config.ban_duration);
+        true
+    }
+
+    pub fn is_banned(&self, peer_id: &str, now: u64) -> bool {
+        self.peers.lock().unwrap().get(peer_id).is_some_and(|standing| is_banned(standing, now))
+    }
+
+    pub fn ban(&self, peer_id: &str, until: u64) {
+        self.peers.lock().unwrap().entry(peer_id.to_string()).or_default().banned_until = Some(until);
+    }
+
+    /// Lifts the ban of `peer_id` and clears its score, returning whether it was
+    /// banned.
+    pub fn unban(&self, peer_id: &str, now: u64) -> bool {
+        self.peers.lock().unwrap().remove(peer_id).is_some_and(|standing| is_banned(&standing, now))
+    }
+
+    /// Peers that misbehaved recently or are banned, as not connected.
+    pub fn penalized(&self, now: u64) -> Vec<PeerStatus> {
+        let mut peers = self.peers.lock().unwrap();
+        // Forget peers that have recovered, so the map does not grow forever.
+        peers.retain(|_, standing| is_banned(standing, now) || self.decayed(standing, now) < -1.0);
+        peers.iter()
+            .map(|(peer_id, standing)| PeerStatus {
+                peer_id: peer_id.clone(),
+                connected: false,
+                score: self.decayed(standing, now),
+                banned_until: standing.banned_until.filter(|until| *until > now),
+            })
+            .collect()
+    }
+
+    /// How strongly to prefer `peer_id` when picking peers, from 1 for a peer about
+    /// to be banned up to minus the threshold for one that never misbehaved.
+    pub fn weight(&self, peer_id: &str, now: u64) -> f64 {
+        (self.score(peer_id, now) - self.config.ban_threshold as f64).max(1.0)
+    }
+
+    fn decayed(&self, standing: &Standing, now: u64) -> f64 {
+        let elapsed = now.saturating_sub(standing.updated) as f64;
+        standing.score * 0.5f64.powf(elapsed / self.config.half_life.max(1) as f64)
+    }
+}
+
+fn is_banned(standing: &Standing, now: u64) -> bool {
+    standing.banned_until.is_some_and(|until| until > now)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reputation_test() {
+        let config = ReputationConfig::default();
+        let reputation = Reputation::new(config);
+        assert_eq!(reputation.score("alice", 0), 0.0);
+
+        assert!(!reputation.penalize("alice", Offense::InvalidBlock, 1000));
+        assert_eq!(reputation.score("alice", 1000), -50.0);
+        // Penalties halve every half-life.
+        assert_eq!(reputation.score("alice", 1000 + config.half_life), -25.0);
+        assert!(reputation.weight("alice", 1000) < reputation.weight("bob", 1000));
+
+        assert!(reputation.penalize("alice", Offense::InvalidBlock, 1000));
+        assert!(reputation.is_banned("alice", 1000));
+        assert!(!reputation.is_banned("alice", 1000 + config.ban_duration));
+        // Already banned.
+        assert!(!reputation.penalize("alice", Offense::MalformedMessage, 1000));
+
+        // Recovered peers are forgotten.
+        assert!(!reputation.penalize("bob", Offense::UnexpectedResponse, 1000));
+        assert_eq!(reputation.penalized(1000).len(), 2);
+        assert_eq!(reputation.penalized(1000 + 10 * config.half_life).len(), 0);
+
+        reputation.ban("carol", 2000);
+        assert!(reputation.is_banned("carol", 1000));
+        assert!(reputation.unban("carol", 1000));
+        assert!(!reputation.is_banned("carol", 1000));
+        assert!(!reputation.unban("carol", 1000));
+    }
+}
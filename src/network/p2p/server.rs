@@ -0,0 +1,197 @@
+//! Answers the requests of peers from the local node.
+
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::thread;
+
+use log::{debug, info, warn};
+use p2p::{Reply, Server};
+
+use crate::biz::consensus::Consensus;
+use crate::biz::peer_client::{Offense, PeerClient};
+use crate::biz::state::State;
+use crate::biz::Node;
+use crate::error::Error;
+use crate::schema::v1::{request, AddressesReq, BlockBody, GetBlockBodiesReq, GetHeadersReq, HandshakeReq, Method, NewBlockReq, NewTxReq, Request, Response};
+use crate::types::Address;
+
+use super::handshake::{LocalNode, PeerInfo};
+use super::P2pClient;
+
+/// Most headers sent in answer to a single `GetHeaders` request.
+pub const MAX_HEADERS_PER_RESPONSE: usize = 512;
+
+/// Most bodies sent in answer to a single `GetBlockBodies` request.
+pub const MAX_BODIES_PER_RESPONSE: usize = 128;
+
+/// Starts serving peers on `addr` in the background and returns the address actually
+/// bound, which differs from `addr` when it asks for any free port.
+///
+/// Announced txs and blocks are relayed through the peer client of `node`.
+pub fn start<S: State, C: Consensus>(addr: SocketAddr, local: LocalNode, node: Node<S, P2pClient<S>, C>) -> Result<SocketAddr, Error> {
+    let server = Server::bind(addr, local.key.clone())?;
+    let addr = server.local_addr()?;
+    info!("P2P server listening on {addr}");
+
+    thread::spawn(move || server.serve(move |peer_addr, remote_key| {
+        let session = Session {
+            peer_addr,
+            remote_key: remote_key.into(),
+            local: local.clone(),
+            node: node.clone(),
+            info: Mutex::new(None),
+        };
+        move |request| session.respond(request)
+    }));
+    Ok(addr)
+}
+
+/// Connection of a peer to the local node.
+struct Session<S: State, C: Consensus> {
+    peer_addr: SocketAddr,
+    /// Key the peer authenticated the connection with.
+    remote_key: Address,
+    local: LocalNode,
+    node: Node<S, P2pClient<S>, C>,
+    /// What was agreed in the handshake, nothing else is served before it.
+    info: Mutex<Option<PeerInfo>>,
+}
+
+impl <S: State, C: Consensus>Session<S, C> {
+    /// Answers `request` under its id, with an error response if it cannot be
+    /// handled. Peers that fail the handshake or are banned are disconnected, and
+    /// peers sending what does not decode reported.
+    fn respond(&self, request: Vec<u8>) -> Reply {
+        let peer_id = self.remote_key.to_string();
+        let request = match Request::try_from(request) {
+            Ok(request) => request,
+            Err(err) => {
+                self.node.peer_client.report(&peer_id, Offense::MalformedMessage);
+                let response = Response::new_error_response(Method::default() as i32, err.to_string()).into();
+                return if self.node.peer_client.is_banned(&peer_id) {
+                    Reply::Close(response)
+                } else {
+                    Reply::Message(response)
+                };
+            }
+        };
+        let (id, method) = (request.id, request.method);
+        let (response, close) = if self.node.peer_client.is_banned(&peer_id) {
+            (Err(Error::PeerBanned(peer_id)), true)
+        } else {
+            match self.handshake(&request) {
+                Some(Ok(response)) => (Ok(response), false),
+                Some(Err(err)) => (Err(err), true),
+                None => (self.handle(request), false),
+            }
+        };
+
+        let mut response = response.unwrap_or_else(|err| {
+            if close {
+                warn!("Disconnected {}: {err}", self.peer_addr);
+            } else {
+                debug!("Failed to handle request {id} of {}: {err}", self.peer_addr);
+            }
+            Response::new_error_response(method, err.to_string())
+        });
+        response.id = id;
+        if close {
+            Reply::Close(response.into())
+        } else {
+            Reply::Message(response.into())
+        }
+    }
+
+    /// Handles `request` if it is a handshake, or fails it if the peer has not
+    /// shaken hands yet. `None` lets the request through.
+    fn handshake(&self, request: &Request) -> Option<Result<Response, Error>> {
+        let mut info = self.info.lock().unwrap();
+        let handshake = match &request.body {
+            Some(request::Body::HandshakeReq(HandshakeReq { node })) => node,
+            _ if info.is_none() => return Some(Err(Error::HandshakeRequired)),
+            _ => return None,
+        };
+        let result = handshake.as_ref()
+            .ok_or(Error::InvalidRequest)
+            .and_then(|handshake| self.local.accept(handshake, self.remote_key))
+            .map(|accepted| {
+                info!("Accepted {}, node {} at height {} speaking protocol version {}",
+                    self.peer_addr, accepted.node_id, accepted.best_height, accepted.protocol_version);
+                *info = Some(accepted);
+                Response::new_handshake_response(self.local.handshake(self.node.state.block_height()))
+            });
+        Some(result)
+    }
+
+    fn handle(&self, request: Request) -> Result<Response, Error> {
+        let node = &self.node;
+        match request.body {
+            Some(request::Body::BlockHeightReq(_)) => {
+                Ok(Response::new_block_height_response(node.state.block_height()))
+            }
+            Some(request::Body::GetHeadersReq(GetHeadersReq { from_height, limit })) => {
+                let limit = (limit as usize).min(MAX_HEADERS_PER_RESPONSE);
+                Ok(Response::new_headers_response(node.state.get_headers(from_height, limit)))
+            }
+            Some(request::Body::GetBlockBodiesReq(GetBlockBodiesReq { hashes })) => {
+                let mut bodies = vec![];
+                for hash in hashes.into_iter().take(MAX_BODIES_PER_RESPONSE) {
+                    let hash: [u8; 32] = hash.try_into().map_err(|_| Error::InvalidRequest)?;
+                    match node.state.get_block_by_hash(&hash.into()) {
+                        Some(block) => bodies.push(BlockBody { txs: block.txs }),
+                        None => break,
+                    }
+                }
+                Ok(Response::new_block_bodies_response(bodies))
+            }
+            // Announcements are acknowledged whether or not the node takes them, a peer
+            // cannot tell what we already have. Only what passes validation is relayed
+            // and remembered as seen: the id does not cover the signature, so a forged
+            // copy must not shadow the real one, and what failed may pass later.
+            Some(request::Body::NewTxReq(NewTxReq { tx: Some(tx) })) => {
+                let id = tx.raw_tx()?.id();
+                if !node.peer_client.is_tx_seen(&id) {
+                    match node.mempool.add(tx.clone(), &node.state) {
+                        Ok(_) => if node.peer_client.mark_tx_seen(id) {
+                            node.peer_client.relay_tx(tx, self.remote_key);
+                        }
+                        Err(err) => debug!("Ignored announced tx {id}: {err}"),
+                    }
+                }
+                Ok(Response::new_ack_response(Method::NewTx))
+            }
+            Some(request::Body::NewBlockReq(NewBlockReq { block: Some(block) })) => {
+                let header = block.header()?.clone();
+                let hash = header.hash();
+                if !node.peer_client.is_block_seen(&hash) {
+                    match node.add_block(block.clone()) {
+                        Ok(_) => {
+                            info!("Added announced block {} {hash}", header.height);
+                            if node.peer_client.mark_block_seen(hash) {
+                                node.peer_client.relay_block(block, self.remote_key);
+                            }
+                        }
+                        Err(err) => {
+                            debug!("Ignored announced block {hash}: {err}");
+                            if let Some(offense) = Offense::of_block_error(&err) {
+                                node.peer_client.report(&self.remote_key.to_string(), offense);
+                            }
+                        }
+                    }
+                }
+                Ok(Response::new_ack_response(Method::NewBlock))
+            }
+            Some(request::Body::GetAddressesReq(_)) => {
+                Ok(Response::new_addresses_response(node.peer_client.shareable_addresses()))
+            }
+            Some(request::Body::AddressesReq(AddressesReq { addresses })) => {
+                let added = node.peer_client.add_addresses(addresses);
+                if added > 0 {
+                    debug!("Learned {added} addresses from {}", self.peer_addr);
+                }
+                Ok(Response::new_ack_response(Method::Addresses))
+            }
+            _ => Err(Error::InvalidRequest),
+        }
+    }
+}
@@ -1,13 +1,17 @@
 use std::fmt;
 
 use prost::Message;
+use serde::{Deserialize, Serialize};
 
+use crate::consensus::{params::ConsensusParams, pow};
 use crate::error::Error;
 use crate::utils::{self, gen_random_number, unix_timestamp};
 use crate::schema::v1::{Block, BlockHeader, SignedTx};
-use crate::types::{Address, Hash};
+use crate::types::{Address, Bytes, Hash, Signature};
 
-const PUZZLE_REWARD: u64 = 25;
+const MERKLE_LEAF_PREFIX: u8 = 0x00;
+
+const MERKLE_NODE_PREFIX: u8 = 0x01;
 
 impl Block {
     pub fn new(header: Option<BlockHeader>, txs: Vec<SignedTx>) -> Self {
@@ -17,13 +21,28 @@ impl Block {
         }
     }
 
-    pub fn block_reward(&self) -> u64 {
-        let txs_pack_reward: u64 = self.txs.iter().map(|tx|match tx.gas_cost() {
-            Ok(cost) => cost,
-            Err(_) => 0,
-        }).sum();
+    /// The minted reward at the height of the block plus the gas its txs paid.
+    pub fn block_reward(&self, params: &ConsensusParams) -> Result<u64, Error> {
+        let height = self.header.as_ref().map(|header| header.height).unwrap_or_default();
+        self.txs.iter().try_fold(params.block_reward_at(height), |reward, tx| {
+            reward.checked_add(tx.gas_cost()?).ok_or(Error::FeeOverflow)
+        })
+    }
+
+    pub fn tx_ids(&self) -> Result<Vec<Hash>, Error> {
+        self.txs.iter().map(|tx| tx.raw_tx_digest()).collect()
+    }
+
+    pub fn compute_txs_root(&self) -> Result<Hash, Error> {
+        Ok(merkle_root(&self.tx_ids()?))
+    }
 
-        txs_pack_reward + PUZZLE_REWARD
+    /// Builds the proof that the tx with `tx_id` is committed to by `txs_root`.
+    pub fn merkle_proof(&self, tx_id: &Hash) -> Result<Option<MerkleProof>, Error> {
+        let tx_ids = self.tx_ids()?;
+        Ok(tx_ids.iter()
+            .position(|id| id == tx_id)
+            .and_then(|index| MerkleProof::generate(&tx_ids, index)))
     }
 
     // pub fn parent_hash(&self) -> Result<Hash, Error> {
@@ -62,6 +81,22 @@ impl Block {
     //     Ok(())
     // }
 
+    #[cfg(test)]
+    pub fn new_child_for_test(parent: Option<&BlockHeader>, author: Address, txs: Vec<SignedTx>) -> Self {
+        let mut block = Block::new(None, txs);
+        let (parent_hash, height) = match parent {
+            Some(parent) => (parent.hash(), parent.height + 1),
+            None => (Hash::default(), 0),
+        };
+        let txs_root = block.compute_txs_root().unwrap();
+        let mut header = BlockHeader::new(parent_hash, height, 0, author, txs_root);
+        if let Some(parent) = parent {
+            header.difficulty = parent.difficulty;
+        }
+        block.header = Some(header);
+        block
+    }
+
     pub fn header(&self) -> Result<&BlockHeader, Error> {
         match &self.header {
             Some(header) => Ok(header),
@@ -94,12 +129,16 @@ impl BlockHeader {
             timestamp: unix_timestamp(),
             author: author.into(),
             txs_root: txs_root.into(),
+            difficulty: pow::MIN_DIFFICULTY,
+            signature: vec![],
         }
     }
 
-    // pub fn parent_hash(&self) -> Hash {
-    //     self.parent_hash.clone().into()
-    // }
+    /// Hash of the parent, `Error::InvalidBlockHeader` if the header carries a
+    /// malformed one.
+    pub fn parent_hash(&self) -> Result<Hash, Error> {
+        self.parent_hash.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidBlockHeader)
+    }
 
     // pub fn height(&self) -> u64 {
     //     self.height
@@ -113,17 +152,31 @@ impl BlockHeader {
     //     self.timestamp
     // }
 
-    // pub fn author(&self) -> Address {
-    //     self.author.clone().into()
-    // }
+    /// Account the block reward goes to, `Error::InvalidBlockHeader` if malformed.
+    pub fn author(&self) -> Result<Address, Error> {
+        self.author.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidBlockHeader)
+    }
 
     pub fn hash(&self) -> Hash {
         utils::hash(&self.encode_to_vec())
     }
 
-    // pub fn txs_root(&self) -> Hash {
-    //     self.txs_root.clone().into()
-    // }
+    /// Hash of the header without its signature, which is what a sealer signs.
+    pub fn seal_hash(&self) -> Hash {
+        let mut header = self.clone();
+        header.signature.clear();
+        header.hash()
+    }
+
+    /// Signature of the sealer, `None` if the header carries no well-formed one.
+    pub fn signature(&self) -> Option<Signature> {
+        self.signature.as_slice().try_into().ok().map(Bytes)
+    }
+
+    /// Merkle root of the tx ids, `Error::InvalidBlockHeader` if malformed.
+    pub fn txs_root(&self) -> Result<Hash, Error> {
+        self.txs_root.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidBlockHeader)
+    }
 
     pub fn update_nonce_and_timestamp(&mut self) {
         self.timestamp = unix_timestamp();
@@ -132,6 +185,93 @@ impl BlockHeader {
 
 }
 
+/// Sibling on the path from a leaf to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleNode {
+    pub hash: Hash,
+    pub is_left: bool,
+}
+
+/// Inclusion proof of one tx id in a merkle root.
+///
+/// A level with an odd number of nodes promotes its last node unchanged, so such a
+/// level contributes no sibling to the path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub leaf: Hash,
+    pub path: Vec<MerkleNode>,
+}
+
+impl MerkleProof {
+    pub fn generate(leaves: &[Hash], index: usize) -> Option<Self> {
+        let leaf = *leaves.get(index)?;
+        let mut level: Vec<Hash> = leaves.iter().map(merkle_leaf).collect();
+        let mut index = index;
+        let mut path = vec![];
+
+        while level.len() > 1 {
+            let sibling = index ^ 1;
+            if let Some(hash) = level.get(sibling) {
+                path.push(MerkleNode { hash: *hash, is_left: sibling < index });
+            }
+            level = merkle_parent_level(&level);
+            index /= 2;
+        }
+
+        Some(MerkleProof { leaf, path })
+    }
+
+    pub fn verify(&self, root: &Hash) -> bool {
+        let computed = self.path.iter().fold(merkle_leaf(&self.leaf), |acc, node| {
+            if node.is_left {
+                merkle_node(&node.hash, &acc)
+            } else {
+                merkle_node(&acc, &node.hash)
+            }
+        });
+        &computed == root
+    }
+}
+
+/// Root of the binary merkle tree over `leaves`, or the empty hash when there are none.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return Hash::default();
+    }
+    let mut level: Vec<Hash> = leaves.iter().map(merkle_leaf).collect();
+    while level.len() > 1 {
+        level = merkle_parent_level(&level);
+    }
+    level[0]
+}
+
+fn merkle_parent_level(level: &[Hash]) -> Vec<Hash> {
+    level.chunks(2)
+        .map(|pair| match pair {
+            [left, right] => merkle_node(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+// Leaves and inner nodes are hashed under different prefixes so that an inner node
+// can never be passed off as a leaf.
+fn merkle_leaf(leaf: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(1 + leaf.len());
+    data.push(MERKLE_LEAF_PREFIX);
+    data.extend_from_slice(leaf.as_slice());
+    utils::hash(&data)
+}
+
+fn merkle_node(left: &Hash, right: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(1 + left.len() + right.len());
+    data.push(MERKLE_NODE_PREFIX);
+    data.extend_from_slice(left.as_slice());
+    data.extend_from_slice(right.as_slice());
+    utils::hash(&data)
+}
+
 impl TryFrom<Vec<u8>> for Block {
     type Error = Error;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
@@ -158,39 +298,34 @@ impl fmt::Display for Block {
 
 impl fmt::Display for BlockHeader {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "BlockHeader: {{ \nparent hash: {:?}, \nheight: {}, \nnonce: {}, \ntimestamp: {}, \nauthor: {:?}, \ntxs root: {:?} \n}}", 
-        self.parent_hash, self.height, self.nonce, self.timestamp, self.author, self.txs_root)
+        write!(f, "BlockHeader: {{ \nparent hash: {:?}, \nheight: {}, \nnonce: {}, \ntimestamp: {}, \nauthor: {:?}, \ntxs root: {:?}, \ndifficulty: {} \n}}", 
+        self.parent_hash, self.height, self.nonce, self.timestamp, self.author, self.txs_root, self.difficulty)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{schema::v1::{Block, BlockHeader, SignedTx, Tx}, types::Bytes, utils};
+    use wallet::Wallet;
+
+    use super::{merkle_root, MerkleProof};
+    use crate::{consensus::params::ConsensusParams, error::Error, schema::v1::{Block, BlockHeader, Tx}, types::{Bytes, Hash}};
 
     #[test]
     fn block_test() {
-        let sender1 = Bytes::<32>::new_for_test();
+        let sender1 = Wallet::new();
         let receiver1 = Bytes::<32>::new_for_test();
-        let signature1 = Bytes::<65>::new_for_test();
 
-        let raw_tx1 = Tx::new(sender1, receiver1, 100, 2);
-        let signed_tx1 = SignedTx::new(Some(raw_tx1), signature1);
+        let raw_tx1 = Tx::new(sender1.address().into(), receiver1, 100, 2);
+        let signed_tx1 = raw_tx1.sign(&sender1);
 
-        let sender2 = Bytes::<32>::new_for_test();
+        let sender2 = Wallet::new();
         let receiver2 = Bytes::<32>::new_for_test();
-        let signature2 = Bytes::<65>::new_for_test();
 
-        let raw_tx2 = Tx::new(sender2, receiver2, 200, 3);
-        let signed_tx2 = SignedTx::new(Some(raw_tx2), signature2);
+        let raw_tx2 = Tx::new(sender2.address().into(), receiver2, 200, 3);
+        let signed_tx2 = raw_tx2.sign(&sender2);
 
         let txs = vec![signed_tx1, signed_tx2];
-        let mut flatten_txs: Vec<u8> = vec![];
-        for tx in txs.clone() {
-            let serialized: Vec<u8> = tx.into();
-            flatten_txs.extend(serialized);
-        }
-
-        let txs_root = utils::hash(&flatten_txs);
+        let txs_root = Block::new(None, txs.clone()).compute_txs_root().unwrap();
         let parent_hash = Bytes::<32>::new_for_test();
         let author = Bytes::<32>::new_for_test();
 
@@ -200,4 +335,57 @@ mod test {
 
         println!("block = {}", block);
     }
+
+    #[test]
+    fn block_reward_test() {
+        let sender = Wallet::new();
+        let params = ConsensusParams::default();
+        let new_tx = |version, gas_price| {
+            let mut tx = Tx::new(sender.address().into(), Bytes::<32>::new_for_test(), 1, version);
+            tx.gas_price = gas_price;
+            tx.sign(&sender)
+        };
+
+        let block = Block::new(None, vec![new_tx(1, 1), new_tx(2, 2)]);
+        assert_eq!(block.block_reward(&params).unwrap(), params.block_reward + 21 + 42);
+
+        let block = Block::new(None, vec![new_tx(1, u64::MAX / 42), new_tx(2, u64::MAX / 42)]);
+        assert!(matches!(block.block_reward(&params), Err(Error::FeeOverflow)));
+    }
+
+    #[test]
+    fn merkle_proof_test() {
+        for count in 1..=9 {
+            let leaves: Vec<Hash> = (0..count).map(|_| Bytes::<32>::new_for_test()).collect();
+            let root = merkle_root(&leaves);
+
+            for index in 0..count {
+                let proof = MerkleProof::generate(&leaves, index).unwrap();
+                assert!(proof.verify(&root));
+
+                let mut forged = proof.clone();
+                forged.leaf = Bytes::<32>::new_for_test();
+                assert!(!forged.verify(&root));
+            }
+            assert!(MerkleProof::generate(&leaves, count).is_none());
+        }
+        assert!(merkle_root(&[]).is_empty());
+    }
+
+    #[test]
+    fn block_merkle_proof_test() {
+        let wallet = Wallet::new();
+        let txs: Vec<_> = (1..=5)
+            .map(|version| Tx::new(wallet.address().into(), Bytes::<32>::new_for_test(), 10, version).sign(&wallet))
+            .collect();
+        let block = Block::new(None, txs.clone());
+        let txs_root = block.compute_txs_root().unwrap();
+
+        let tx_id = txs[3].raw_tx_digest().unwrap();
+        let proof = block.merkle_proof(&tx_id).unwrap().unwrap();
+        assert_eq!(proof.leaf, tx_id);
+        assert!(proof.verify(&txs_root));
+
+        assert!(block.merkle_proof(&Bytes::<32>::new_for_test()).unwrap().is_none());
+    }
 }
\ No newline at end of file
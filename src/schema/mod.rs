@@ -1,3 +1,5 @@
+// Generated by prost from api.v1.proto, which names every request body `*Req`.
+#[allow(clippy::enum_variant_names)]
 pub mod v1;
 pub mod block;
 pub mod tx;
@@ -5,28 +5,92 @@ use crate::error::Error;
 use super::v1::{
     request, 
     response, 
+    AddressesReq, 
+    AddressesResp, 
     Block, 
+    BlockBodiesResp, 
+    BlockBody, 
+    BlockHeader, 
     BlockHeightReq, 
     BlockHeightResp, 
-    BlocksReq, 
-    BlocksResp, 
+    ErrorResp, 
+    GetAddressesReq, 
+    GetBlockBodiesReq, 
+    GetHeadersReq, 
+    HandshakeReq, 
+    HandshakeResp, 
+    HeadersResp, 
     Method, 
+    NewBlockReq, 
+    NewTxReq, 
+    NodeInfo, 
     Request, 
-    Response
+    Response, 
+    SignedTx
 };
 
 impl Request {
     pub fn new_block_height_request() -> Self {
         Request {
+            id: 0,
             method: Method::Height as i32,
             body: Some(request::Body::BlockHeightReq(BlockHeightReq{}))
         }
     } 
 
-    pub fn new_blocks_request(from_height: u64) -> Self {
-        Request { 
-            method: Method::Blocks as i32, 
-            body: Some(request::Body::BlocksReq(BlocksReq{from_height}))
+    pub fn new_get_headers_request(from_height: u64, limit: u32) -> Self {
+        Request {
+            id: 0,
+            method: Method::GetHeaders as i32,
+            body: Some(request::Body::GetHeadersReq(GetHeadersReq{from_height, limit}))
+        }
+    }
+
+    pub fn new_get_block_bodies_request(hashes: Vec<Vec<u8>>) -> Self {
+        Request {
+            id: 0,
+            method: Method::GetBlockBodies as i32,
+            body: Some(request::Body::GetBlockBodiesReq(GetBlockBodiesReq{hashes}))
+        }
+    }
+
+    pub fn new_tx_request(tx: SignedTx) -> Self {
+        Request {
+            id: 0,
+            method: Method::NewTx as i32,
+            body: Some(request::Body::NewTxReq(NewTxReq{tx: Some(tx)}))
+        }
+    }
+
+    pub fn new_block_request(block: Block) -> Self {
+        Request {
+            id: 0,
+            method: Method::NewBlock as i32,
+            body: Some(request::Body::NewBlockReq(NewBlockReq{block: Some(block)}))
+        }
+    }
+
+    pub fn new_handshake_request(node: NodeInfo) -> Self {
+        Request {
+            id: 0,
+            method: Method::Handshake as i32,
+            body: Some(request::Body::HandshakeReq(HandshakeReq{node: Some(node)}))
+        }
+    }
+
+    pub fn new_get_addresses_request() -> Self {
+        Request {
+            id: 0,
+            method: Method::GetAddresses as i32,
+            body: Some(request::Body::GetAddressesReq(GetAddressesReq{}))
+        }
+    }
+
+    pub fn new_addresses_request(addresses: Vec<String>) -> Self {
+        Request {
+            id: 0,
+            method: Method::Addresses as i32,
+            body: Some(request::Body::AddressesReq(AddressesReq{addresses}))
         }
     }
 }
@@ -34,15 +98,66 @@ impl Request {
 impl Response {
     pub fn new_block_height_response(height: u64) -> Self {
         Response { 
+            id: 0,
             method: Method::Height as i32, 
             body: Some(response::Body::BlockHeightResp(BlockHeightResp{height})) 
         }
     }
 
-    pub fn new_blocks_response(blocks: Vec<Block>) -> Self {
-        Response{
-            method: Method::Blocks as i32,
-            body: Some(response::Body::BlocksResp(BlocksResp{blocks}))
+    pub fn new_headers_response(headers: Vec<BlockHeader>) -> Self {
+        Response {
+            id: 0,
+            method: Method::GetHeaders as i32,
+            body: Some(response::Body::HeadersResp(HeadersResp{headers}))
+        }
+    }
+
+    pub fn new_block_bodies_response(bodies: Vec<BlockBody>) -> Self {
+        Response {
+            id: 0,
+            method: Method::GetBlockBodies as i32,
+            body: Some(response::Body::BlockBodiesResp(BlockBodiesResp{bodies}))
+        }
+    }
+
+    pub fn new_handshake_response(node: NodeInfo) -> Self {
+        Response {
+            id: 0,
+            method: Method::Handshake as i32,
+            body: Some(response::Body::HandshakeResp(HandshakeResp{node: Some(node)}))
+        }
+    }
+
+    pub fn new_addresses_response(addresses: Vec<String>) -> Self {
+        Response {
+            id: 0,
+            method: Method::GetAddresses as i32,
+            body: Some(response::Body::AddressesResp(AddressesResp{addresses}))
+        }
+    }
+
+    /// Acknowledges an announcement made with `method`.
+    pub fn new_ack_response(method: Method) -> Self {
+        Response {
+            id: 0,
+            method: method as i32,
+            body: None
+        }
+    }
+
+    pub fn new_error_response(method: i32, message: String) -> Self {
+        Response {
+            id: 0,
+            method,
+            body: Some(response::Body::ErrorResp(ErrorResp{message}))
+        }
+    }
+
+    /// The response itself, or `Error::PeerFailed` if it reports an error.
+    pub fn into_result(self, peer_id: &str) -> Result<Self, Error> {
+        match self.body {
+            Some(response::Body::ErrorResp(ErrorResp{message})) => Err(Error::PeerFailed { peer_id: peer_id.to_string(), message }),
+            _ => Ok(self),
         }
     }
 }
@@ -77,28 +192,48 @@ impl TryFrom<Response> for BlockHeightResp {
     type Error = Error;
     fn try_from(value: Response) -> Result<Self, Self::Error> {
         match value.body {
-            Some(body) => {
-                match body {
-                    response::Body::BlockHeightResp(resp) => Ok(resp),
-                    response::Body::BlocksResp(_) => Err(Error::InvalidResponse),
-                }
-            },
-            None => Err(Error::InvalidResponse)
+            Some(response::Body::BlockHeightResp(resp)) => Ok(resp),
+            _ => Err(Error::InvalidResponse)
+        }
+    }
+}
+
+impl TryFrom<Response> for HeadersResp {
+    type Error = Error;
+    fn try_from(value: Response) -> Result<Self, Self::Error> {
+        match value.body {
+            Some(response::Body::HeadersResp(resp)) => Ok(resp),
+            _ => Err(Error::InvalidResponse)
+        }
+    }
+}
+
+impl TryFrom<Response> for BlockBodiesResp {
+    type Error = Error;
+    fn try_from(value: Response) -> Result<Self, Self::Error> {
+        match value.body {
+            Some(response::Body::BlockBodiesResp(resp)) => Ok(resp),
+            _ => Err(Error::InvalidResponse)
+        }
+    }
+}
+
+impl TryFrom<Response> for HandshakeResp {
+    type Error = Error;
+    fn try_from(value: Response) -> Result<Self, Self::Error> {
+        match value.body {
+            Some(response::Body::HandshakeResp(resp)) => Ok(resp),
+            _ => Err(Error::InvalidResponse)
         }
     }
 }
 
-impl TryFrom<Response> for BlocksResp {
+impl TryFrom<Response> for AddressesResp {
     type Error = Error;
     fn try_from(value: Response) -> Result<Self, Self::Error> {
         match value.body {
-            Some(body) => {
-                match body {
-                    response::Body::BlockHeightResp(_) => Err(Error::InvalidResponse),
-                    response::Body::BlocksResp(resp) => Ok(resp),
-                }
-            }
-            None => Err(Error::InvalidResponse)
+            Some(response::Body::AddressesResp(resp)) => Ok(resp),
+            _ => Err(Error::InvalidResponse)
         }
     }
 }
@@ -1,15 +1,12 @@
 use core::fmt;
 
 use prost::Message;
+use wallet::Wallet;
 
-use crate::{error::Error, utils, types::{Address, Hash, Signature}};
+use crate::{consensus::params::ConsensusParams, error::Error, utils, types::{Address, Bytes, Hash, Signature}};
 
 use super::v1::{SignedTx, Tx};
 
-const GAS: u64 = 21;
-
-const GAS_PRICE: u64 = 1;
-
 impl SignedTx {
     pub fn new(tx: Option<Tx>, signature: Signature) -> Self {
         let signature = signature.to_vec();
@@ -19,8 +16,9 @@ impl SignedTx {
         }
     }
 
-    pub fn signature(&self) -> Signature {
-        self.signature.clone().into()
+    /// Signature over the tx, `Error::InvalidTx` if malformed.
+    pub fn signature(&self) -> Result<Signature, Error> {
+        self.signature.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidTx)
     }
 
     // pub fn sender(&self) -> Result<Vec<u8>, Error> {
@@ -54,8 +52,7 @@ impl SignedTx {
     // }
 
     pub fn gas_cost(&self) -> Result<u64, Error> {
-        let raw_tx = self.raw_tx()?;
-        Ok(raw_tx.gas_cost())
+        self.raw_tx()?.gas_cost()
     }
 
     // pub fn timestamp(&self) -> Result<u64, Error> {
@@ -64,8 +61,7 @@ impl SignedTx {
     // } 
 
     pub fn total_cost(&self) -> Result<u64, Error> {
-        let raw_tx = self.raw_tx()?;
-        Ok(raw_tx.total_cost())
+        self.raw_tx()?.total_cost()
     }
 
     pub fn raw_tx_digest(&self) -> Result<Hash, Error> {
@@ -73,6 +69,25 @@ impl SignedTx {
         Ok(raw_tx.id())
     }
 
+    /// Recovers the address that produced `signature` over `raw_tx_digest()`.
+    pub fn recover_signer(&self) -> Result<Address, Error> {
+        let digest = self.raw_tx_digest()?;
+        let signer = wallet::recover_address(&digest, &self.signature()?.0)?;
+        Ok(signer.into())
+    }
+
+    /// Checks that the tx is well-formed and signed by its sender.
+    pub fn verify(&self) -> Result<(), Error> {
+        let raw_tx = self.raw_tx()?;
+        let sender = raw_tx.sender()?;
+        raw_tx.receiver()?;
+        let signer = self.recover_signer()?;
+        if signer != sender {
+            return Err(Error::InvalidSigner { sender, signer });
+        }
+        Ok(())
+    }
+
     pub fn raw_tx(&self) -> Result<&Tx, Error> {
         match &self.tx {
             Some(tx) => Ok(tx),
@@ -101,6 +116,7 @@ impl fmt::Display for SignedTx {
 }
 
 impl Tx {
+    /// A transfer paying the gas and the minimum gas price of the default params.
     pub fn new(
         sender: Address, 
         receiver: Address, 
@@ -110,24 +126,27 @@ impl Tx {
         //gas_price: u64,
         //timestamp: u64,
     ) -> Self {
+        let params = ConsensusParams::default();
         Tx { 
             sender: sender.into(), 
             receiver: receiver.into(), 
             amount, 
             version, 
-            gas: GAS,
-            gas_price: GAS_PRICE,
+            gas: params.transfer_gas,
+            gas_price: params.min_gas_price,
             timestamp: utils::unix_timestamp(), 
         }
     }
 
-    // pub fn receiver(&self) -> Vec<u8> {
-    //     self.receiver.clone()
-    // }
+    /// `Error::InvalidTx` if malformed.
+    pub fn receiver(&self) -> Result<Address, Error> {
+        self.receiver.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidTx)
+    }
 
-    // pub fn sender(&self) -> Vec<u8> {
-    //     self.sender.clone()
-    // }
+    /// `Error::InvalidTx` if malformed.
+    pub fn sender(&self) -> Result<Address, Error> {
+        self.sender.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidTx)
+    }
 
     // pub fn amount(&self) -> u64 {
     //     self.amount
@@ -149,18 +168,24 @@ impl Tx {
     //     self.timestamp
     // }
 
-    pub fn gas_cost(&self) -> u64 {
-        self.gas * self.gas_price
+    /// `Error::FeeOverflow` if the gas price is too high to pay for.
+    pub fn gas_cost(&self) -> Result<u64, Error> {
+        self.gas.checked_mul(self.gas_price).ok_or(Error::FeeOverflow)
     }
 
-    pub fn total_cost(&self) -> u64 {
-        self.amount + self.gas_cost()
+    pub fn total_cost(&self) -> Result<u64, Error> {
+        self.amount.checked_add(self.gas_cost()?).ok_or(Error::FeeOverflow)
     }
 
     pub fn id(&self) -> Hash {
         utils::hash(&self.as_bytes())
     }
 
+    pub fn sign(self, wallet: &Wallet) -> SignedTx {
+        let signature = wallet.sign(&self.id());
+        SignedTx::new(Some(self), signature.into())
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         self.encode_to_vec()
     }
@@ -179,12 +204,14 @@ impl From<Tx> for Vec<u8> {
     }
 }
 
+#[cfg(test)]
 mod test {
+    use wallet::Wallet;
+
+    use super::*;
+
     #[test]
     fn tx_format_test() {
-        use crate::types::Bytes;
-        use super::*;
-        
         let sender = Bytes::<32>::try_from(
             "0x000036755a024ef491b6710fe765e06e33a616f83b8a33c6a1963ab20f6e5bdb".to_string()
         ).unwrap();
@@ -201,7 +228,55 @@ mod test {
 
         //assert_eq!(signed_tx.amount().unwrap(), 100);
         assert_eq!(raw_tx.amount, 100);
-        assert_eq!(raw_tx.gas_cost(), 21);
-        assert_eq!(raw_tx.total_cost(), 121);
+        assert_eq!(raw_tx.gas_cost().unwrap(), 21);
+        assert_eq!(raw_tx.total_cost().unwrap(), 121);
+
+        let mut expensive = raw_tx.clone();
+        expensive.gas_price = u64::MAX / 21 + 1;
+        assert!(matches!(expensive.gas_cost(), Err(Error::FeeOverflow)));
+        expensive.gas_price = u64::MAX / 21;
+        assert!(matches!(expensive.total_cost(), Err(Error::FeeOverflow)));
+    }
+
+    #[test]
+    fn tx_sign_test() {
+        let wallet = Wallet::new();
+        let receiver = Bytes::<32>::new_for_test();
+
+        let raw_tx = Tx::new(wallet.address().into(), receiver, 100, 1);
+        let signed_tx = raw_tx.clone().sign(&wallet);
+
+        assert_eq!(signed_tx.raw_tx().unwrap(), &raw_tx);
+        assert_eq!(signed_tx.signature().unwrap(), Signature::from(wallet.sign(&raw_tx.id())));
+    }
+
+    #[test]
+    fn tx_verify_test() {
+        let wallet = Wallet::new();
+        let receiver = Bytes::<32>::new_for_test();
+
+        let signed_tx = Tx::new(wallet.address().into(), receiver, 100, 1).sign(&wallet);
+        assert!(signed_tx.verify().is_ok());
+        assert_eq!(signed_tx.recover_signer().unwrap(), Address::from(wallet.address()));
+
+        let forged_tx = Tx::new(receiver, wallet.address().into(), 100, 1).sign(&wallet);
+        assert!(matches!(forged_tx.verify(), Err(Error::InvalidSigner { .. })));
+
+        let mut tampered_tx = signed_tx.clone();
+        tampered_tx.tx.as_mut().unwrap().amount = 1000;
+        assert!(tampered_tx.verify().is_err());
+
+        let unsigned_tx = SignedTx::new(signed_tx.tx.clone(), Bytes::<65>::default());
+        assert!(matches!(unsigned_tx.verify(), Err(Error::InvalidSignature(_))));
+
+        let mut malformed_tx = signed_tx.clone();
+        malformed_tx.tx.as_mut().unwrap().sender.truncate(31);
+        assert!(matches!(malformed_tx.verify(), Err(Error::InvalidTx)));
+        let mut malformed_tx = signed_tx.clone();
+        malformed_tx.tx.as_mut().unwrap().receiver.clear();
+        assert!(matches!(malformed_tx.verify(), Err(Error::InvalidTx)));
+        let mut malformed_tx = signed_tx.clone();
+        malformed_tx.signature.pop();
+        assert!(matches!(malformed_tx.verify(), Err(Error::InvalidTx)));
     }
 }
\ No newline at end of file
@@ -20,6 +20,10 @@ pub struct BlockHeader {
     pub author: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "6")]
     pub txs_root: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "7")]
+    pub difficulty: u64,
+    #[prost(bytes = "vec", tag = "8")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SignedTx {
@@ -45,35 +49,103 @@ pub struct Tx {
     #[prost(uint64, tag = "7")]
     pub timestamp: u64,
 }
-#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+/// Several requests may be in flight on one connection; the response to a request
+/// carries the same id.
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Request {
     #[prost(enumeration = "Method", tag = "1")]
     pub method: i32,
-    #[prost(oneof = "request::Body", tags = "2, 3")]
+    #[prost(uint64, tag = "6")]
+    pub id: u64,
+    #[prost(oneof = "request::Body", tags = "2, 4, 5, 7, 8, 9, 10, 11")]
     pub body: ::core::option::Option<request::Body>,
 }
 /// Nested message and enum types in `Request`.
 pub mod request {
-    #[derive(Clone, Copy, PartialEq, ::prost::Oneof)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Body {
         #[prost(message, tag = "2")]
         BlockHeightReq(super::BlockHeightReq),
-        #[prost(message, tag = "3")]
-        BlocksReq(super::BlocksReq),
+        #[prost(message, tag = "4")]
+        NewTxReq(super::NewTxReq),
+        #[prost(message, tag = "5")]
+        NewBlockReq(super::NewBlockReq),
+        #[prost(message, tag = "7")]
+        HandshakeReq(super::HandshakeReq),
+        #[prost(message, tag = "8")]
+        GetAddressesReq(super::GetAddressesReq),
+        #[prost(message, tag = "9")]
+        AddressesReq(super::AddressesReq),
+        #[prost(message, tag = "10")]
+        GetHeadersReq(super::GetHeadersReq),
+        #[prost(message, tag = "11")]
+        GetBlockBodiesReq(super::GetBlockBodiesReq),
     }
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct BlockHeightReq {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NewTxReq {
+    #[prost(message, optional, tag = "1")]
+    pub tx: ::core::option::Option<SignedTx>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NewBlockReq {
+    #[prost(message, optional, tag = "1")]
+    pub block: ::core::option::Option<Block>,
+}
+/// What a node tells about itself in the handshake opening a connection. The side
+/// that connected sends it first and nothing else is served before it.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NodeInfo {
+    #[prost(uint32, tag = "1")]
+    pub protocol_version: u32,
+    #[prost(string, tag = "2")]
+    pub chain_id: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub genesis_hash: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "4")]
+    pub best_height: u64,
+    #[prost(bytes = "vec", tag = "5")]
+    pub node_id: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HandshakeReq {
+    #[prost(message, optional, tag = "1")]
+    pub node: ::core::option::Option<NodeInfo>,
+}
+/// Asks for the headers of the best chain from `from_height` on, at most `limit` of
+/// them. The peer may send fewer, down to none past its best block.
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
-pub struct BlocksReq {
+pub struct GetHeadersReq {
     #[prost(uint64, tag = "1")]
     pub from_height: u64,
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
 }
+/// Asks for the txs of the blocks with the given hashes, on any branch.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetBlockBodiesReq {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub hashes: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+/// Asks a peer for addresses of other nodes it knows of.
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct GetAddressesReq {}
+/// Announces addresses nodes can be reached at, typically the sender's own.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddressesReq {
+    #[prost(string, repeated, tag = "1")]
+    pub addresses: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Announcements of a new tx or block are acknowledged with an empty body.
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Response {
     #[prost(enumeration = "Method", tag = "1")]
     pub method: i32,
-    #[prost(oneof = "response::Body", tags = "2, 3")]
+    #[prost(uint64, tag = "5")]
+    pub id: u64,
+    #[prost(oneof = "response::Body", tags = "2, 4, 6, 7, 8, 9")]
     pub body: ::core::option::Option<response::Body>,
 }
 /// Nested message and enum types in `Response`.
@@ -82,8 +154,16 @@ pub mod response {
     pub enum Body {
         #[prost(message, tag = "2")]
         BlockHeightResp(super::BlockHeightResp),
-        #[prost(message, tag = "3")]
-        BlocksResp(super::BlocksResp),
+        #[prost(message, tag = "4")]
+        ErrorResp(super::ErrorResp),
+        #[prost(message, tag = "6")]
+        HandshakeResp(super::HandshakeResp),
+        #[prost(message, tag = "7")]
+        AddressesResp(super::AddressesResp),
+        #[prost(message, tag = "8")]
+        HeadersResp(super::HeadersResp),
+        #[prost(message, tag = "9")]
+        BlockBodiesResp(super::BlockBodiesResp),
     }
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
@@ -92,15 +172,49 @@ pub struct BlockHeightResp {
     pub height: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
-pub struct BlocksResp {
+pub struct HeadersResp {
     #[prost(message, repeated, tag = "1")]
-    pub blocks: ::prost::alloc::vec::Vec<Block>,
+    pub headers: ::prost::alloc::vec::Vec<BlockHeader>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BlockBody {
+    #[prost(message, repeated, tag = "1")]
+    pub txs: ::prost::alloc::vec::Vec<SignedTx>,
+}
+/// Bodies in the order their hashes were asked for, ending early at the first block
+/// the peer does not have or once the response is full.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BlockBodiesResp {
+    #[prost(message, repeated, tag = "1")]
+    pub bodies: ::prost::alloc::vec::Vec<BlockBody>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HandshakeResp {
+    #[prost(message, optional, tag = "1")]
+    pub node: ::core::option::Option<NodeInfo>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddressesResp {
+    #[prost(string, repeated, tag = "1")]
+    pub addresses: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Why the peer could not handle the request.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ErrorResp {
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum Method {
     Height = 0,
-    Blocks = 1,
+    NewTx = 2,
+    NewBlock = 3,
+    Handshake = 4,
+    GetAddresses = 5,
+    Addresses = 6,
+    GetHeaders = 7,
+    GetBlockBodies = 8,
 }
 impl Method {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -110,14 +224,26 @@ impl Method {
     pub fn as_str_name(&self) -> &'static str {
         match self {
             Self::Height => "Height",
-            Self::Blocks => "Blocks",
+            Self::NewTx => "NewTx",
+            Self::NewBlock => "NewBlock",
+            Self::Handshake => "Handshake",
+            Self::GetAddresses => "GetAddresses",
+            Self::Addresses => "Addresses",
+            Self::GetHeaders => "GetHeaders",
+            Self::GetBlockBodies => "GetBlockBodies",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
     pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
         match value {
             "Height" => Some(Self::Height),
-            "Blocks" => Some(Self::Blocks),
+            "NewTx" => Some(Self::NewTx),
+            "NewBlock" => Some(Self::NewBlock),
+            "Handshake" => Some(Self::Handshake),
+            "GetAddresses" => Some(Self::GetAddresses),
+            "Addresses" => Some(Self::Addresses),
+            "GetHeaders" => Some(Self::GetHeaders),
+            "GetBlockBodies" => Some(Self::GetBlockBodies),
             _ => None,
         }
     }
@@ -18,7 +18,7 @@ pub type Signature = Bytes<SIGNATURE_LENGTH>;
 
 pub type Address = Bytes<ADDRESS_LENGTH>;
 
-#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, std::hash::Hash, Deserialize, Serialize)]
 #[serde(try_from = "String", into = "String")]
 pub struct Bytes<const T: usize> (pub [u8; T]);
 
@@ -39,9 +39,8 @@ impl <const T: usize>Bytes<T> {
     pub fn new_for_test() -> Self {
         use crate::utils;
         let mut inner = [0u8; T];
-        for i in 0..T {
-            let hex_num = utils::gen_random_number::<u8>();
-            inner[i] = hex_num;
+        for byte in inner.iter_mut() {
+            *byte = utils::gen_random_number::<u8>();
         };
         Bytes(inner)
     }
@@ -54,12 +53,11 @@ impl <const T: usize>Deref for Bytes<T> {
     }
 }
 
-impl <const T: usize>From<Vec<u8>> for Bytes<T> {
-    fn from(value: Vec<u8>) -> Self {
-        let mut inner = [0u8; T];
-        inner.copy_from_slice(value.as_slice());
-        Self(inner)
+impl <const T: usize>TryFrom<Vec<u8>> for Bytes<T> {
+    type Error = Error;
 
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        value.as_slice().try_into().map(Bytes).map_err(|_| Error::InvalidTx)
     }
 }
 
@@ -77,7 +75,10 @@ impl <const T: usize>TryFrom<String> for Bytes<T> {
         }else {
             &value
         };
-        Ok(hex::decode(value).map(Self::from)?)
+        let inner: [u8; T] = hex::decode(value)?
+            .try_into()
+            .map_err(|_| hex::FromHexError::InvalidStringLength)?;
+        Ok(Self(inner))
     }
 }
 
@@ -137,11 +138,13 @@ mod test{
         let b1 = Bytes::<32>::from(arr);
         assert_eq!(b1[..], arr[..]);
 
-        let b2 = Bytes::<32>::from(v);
+        let b2 = Bytes::<32>::try_from(v.clone()).unwrap();
         assert_eq!(b1, b2);
+        assert!(matches!(Bytes::<32>::try_from(v[..31].to_vec()), Err(Error::InvalidTx)));
 
         let b3 = Bytes::<32>::try_from(s.to_string()).unwrap();
         assert_eq!(b1, b3);
+        assert!(Bytes::<32>::try_from(s[..64].to_string()).is_err());
     }
 
     #[test]
@@ -1,5 +1,117 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+//! Key management and recoverable secp256k1 signatures.
+
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    rand::rngs::OsRng,
+    Message,
+    PublicKey,
+    Secp256k1,
+    SecretKey
+};
+use thiserror::Error;
+use tiny_keccak::{Hasher, Sha3};
+
+pub const SECRET_KEY_LENGTH: usize = 32;
+
+pub const SIGNATURE_LENGTH: usize = 65;
+
+pub const ADDRESS_LENGTH: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to decode secret key from hex")]
+    InvalidHex(#[from] hex::FromHexError),
+
+    #[error(transparent)]
+    InvalidKey(#[from] secp256k1::Error),
+
+    #[error("Invalid recovery id {0}")]
+    InvalidRecoveryId(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct Wallet {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+}
+
+impl Wallet {
+    pub fn new() -> Self {
+        let secp = Secp256k1::new();
+        let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
+        Wallet { secret_key, public_key }
+    }
+
+    pub fn from_secret_key(secret_key: &[u8]) -> Result<Self, Error> {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(secret_key)?;
+        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        Ok(Wallet { secret_key, public_key })
+    }
+
+    pub fn from_hex(secret_key: &str) -> Result<Self, Error> {
+        let secret_key = secret_key.strip_prefix("0x").unwrap_or(secret_key);
+        Self::from_secret_key(&hex::decode(secret_key)?)
+    }
+
+    pub fn secret_key(&self) -> [u8; SECRET_KEY_LENGTH] {
+        self.secret_key.secret_bytes()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    pub fn address(&self) -> [u8; ADDRESS_LENGTH] {
+        public_key_to_address(&self.public_key)
+    }
+
+    /// Signs a 32-byte digest, returning `r || s || recovery_id`.
+    pub fn sign(&self, digest: &[u8; 32]) -> [u8; SIGNATURE_LENGTH] {
+        let secp = Secp256k1::signing_only();
+        let message = Message::from_digest(*digest);
+        let signature = secp.sign_ecdsa_recoverable(&message, &self.secret_key);
+        encode_signature(&signature)
+    }
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Address is the SHA3-256 of the uncompressed public key without its `0x04` prefix.
+pub fn public_key_to_address(public_key: &PublicKey) -> [u8; ADDRESS_LENGTH] {
+    let serialized = public_key.serialize_uncompressed();
+    let mut sha3 = Sha3::v256();
+    let mut output = [0u8; ADDRESS_LENGTH];
+    sha3.update(&serialized[1..]);
+    sha3.finalize(&mut output);
+    output
+}
+
+/// Recovers the signer address from a digest and a `r || s || recovery_id` signature.
+pub fn recover_address(digest: &[u8; 32], signature: &[u8; SIGNATURE_LENGTH]) -> Result<[u8; ADDRESS_LENGTH], Error> {
+    let signature = decode_signature(signature)?;
+    let secp = Secp256k1::verification_only();
+    let message = Message::from_digest(*digest);
+    let public_key = secp.recover_ecdsa(&message, &signature)?;
+    Ok(public_key_to_address(&public_key))
+}
+
+fn encode_signature(signature: &RecoverableSignature) -> [u8; SIGNATURE_LENGTH] {
+    let (recovery_id, compact) = signature.serialize_compact();
+    let mut output = [0u8; SIGNATURE_LENGTH];
+    output[..64].copy_from_slice(&compact);
+    output[64] = recovery_id.to_i32() as u8;
+    output
+}
+
+fn decode_signature(signature: &[u8; SIGNATURE_LENGTH]) -> Result<RecoverableSignature, Error> {
+    let recovery_id = RecoveryId::from_i32(signature[64] as i32)
+        .map_err(|_| Error::InvalidRecoveryId(signature[64]))?;
+    Ok(RecoverableSignature::from_compact(&signature[..64], recovery_id)?)
 }
 
 #[cfg(test)]
@@ -7,8 +119,38 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn from_secret_key_test() {
+        let wallet = Wallet::new();
+        let restored = Wallet::from_secret_key(&wallet.secret_key()).unwrap();
+        assert_eq!(wallet.address(), restored.address());
+
+        let restored = Wallet::from_hex(&format!("0x{}", hex::encode(wallet.secret_key()))).unwrap();
+        assert_eq!(wallet.address(), restored.address());
+
+        assert!(Wallet::from_secret_key(&[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn sign_test() {
+        let wallet = Wallet::new();
+        let digest = [7u8; 32];
+
+        let signature = wallet.sign(&digest);
+        assert!(signature[64] < 4);
+        assert_eq!(signature, wallet.sign(&digest));
+        assert_ne!(signature, wallet.sign(&[8u8; 32]));
+    }
+
+    #[test]
+    fn recover_address_test() {
+        let wallet = Wallet::new();
+        let digest = [7u8; 32];
+        let mut signature = wallet.sign(&digest);
+
+        assert_eq!(recover_address(&digest, &signature).unwrap(), wallet.address());
+        assert_ne!(recover_address(&[8u8; 32], &signature).unwrap(), wallet.address());
+
+        signature[64] = 9;
+        assert!(matches!(recover_address(&digest, &signature), Err(Error::InvalidRecoveryId(9))));
     }
 }